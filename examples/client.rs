@@ -1,22 +1,17 @@
 use mosaic_core::*;
 use mosaic_net::*;
-use std::net::SocketAddr;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client_secret_key = SecretKey::generate();
     println!("Client public key: {}", client_secret_key.public());
 
-    let server_public_key =
-        PublicKey::from_printable("mopub03ctpjer5jfkd49rxe4767hk9ij6f8sdtryjnnru1bpwxhcykk54o")?;
+    let server_locator: ServerLocator =
+        "mosaic://mopub03ctpjer5jfkd49rxe4767hk9ij6f8sdtryjnnru1bpwxhcykk54o@127.0.0.1:8081"
+            .parse()?;
 
-    let server_socket: SocketAddr = "127.0.0.1:8081".parse()?;
-
-    let client_config = ClientConfig::new(
-        server_public_key,
-        server_socket,
-        Some(client_secret_key.clone()),
-    )?;
+    let client_config =
+        ClientConfig::from_locator(&server_locator, Some(client_secret_key.clone()))?;
 
     let client = client_config.client(None).await?;
 
@@ -54,7 +49,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    client.close(0, b"client is done").await;
+    client.close(CloseCode::Normal, b"client is done").await;
 
     Ok(())
 }
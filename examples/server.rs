@@ -11,6 +11,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let server_socket: SocketAddr = "127.0.0.1:8081".parse()?;
     println!("SERVER ENDPOINT IS {}", server_socket);
+    println!(
+        "SERVER LOCATOR IS {}",
+        ServerLocator::new(
+            secret_key.public(),
+            &server_socket.ip().to_string(),
+            server_socket.port()
+        )?
+    );
 
     let server_config = ServerConfig::new(secret_key, server_socket)?;
 
@@ -40,7 +48,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    server.shut_down(0, b"Shutting down").await;
+    server
+        .shut_down(CloseCode::ShuttingDown, b"Shutting down")
+        .await;
     eprintln!("Shut down.");
 
     Ok(())
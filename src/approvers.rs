@@ -0,0 +1,285 @@
+use crate::server::{Approval, Approver};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// How severe a decision is, for combining decisions. Approving is the least
+// severe and silently refusing is the most severe, since a source we would
+// refuse silently is one we do not want to tell anything.
+fn severity(approval: Approval) -> u8 {
+    match approval {
+        Approval::Approve => 0,
+        Approval::Refuse => 1,
+        Approval::SilentlyRefuse => 2,
+    }
+}
+
+impl<T: Approver + ?Sized> Approver for Arc<T> {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        (**self).is_client_allowed(s)
+    }
+}
+
+/// An `Approver` that consults a sequence of `Approver`s in order. The first
+/// refusal is returned without consulting the rest. If none refuse, the
+/// client is approved. This is `ApproverExt::first_refusal` over any number
+/// of `Approver`s.
+///
+/// Put cheap or stateless approvers (like a `CidrApprover`) before stateful
+/// ones (like a `RateLimitApprover`) so refused clients do not use up budget.
+#[derive(Default)]
+pub struct ApproverChain {
+    approvers: Vec<Box<dyn Approver>>,
+}
+
+impl std::fmt::Debug for ApproverChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApproverChain")
+            .field("approvers", &self.approvers.len())
+            .finish()
+    }
+}
+
+impl ApproverChain {
+    /// Create an empty `ApproverChain`, which approves everybody
+    #[must_use]
+    pub fn new() -> ApproverChain {
+        ApproverChain::default()
+    }
+
+    /// Add an `Approver` to the end of the chain
+    pub fn push<A: Approver + 'static>(&mut self, approver: A) {
+        self.approvers.push(Box::new(approver));
+    }
+}
+
+impl Approver for ApproverChain {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        self.approvers
+            .iter()
+            .map(|approver| approver.is_client_allowed(s))
+            .find(|approval| *approval != Approval::Approve)
+            .unwrap_or(Approval::Approve)
+    }
+}
+
+/// Combinators for composing `Approver`s
+///
+/// When refusals are combined, `Approval::SilentlyRefuse` is considered
+/// stricter than `Approval::Refuse`.
+pub trait ApproverExt: Approver + Sized {
+    /// Approve only if both approve. Both are always consulted, and the
+    /// strictest decision is returned.
+    #[must_use]
+    fn and<B: Approver>(self, other: B) -> And<Self, B> {
+        And(self, other)
+    }
+
+    /// Approve if either approves. `other` is only consulted if `self`
+    /// refuses. If both refuse, the more lenient refusal is returned.
+    #[must_use]
+    fn or<B: Approver>(self, other: B) -> Or<Self, B> {
+        Or(self, other)
+    }
+
+    /// Approve only if both approve. `other` is only consulted if `self`
+    /// approves, otherwise `self`'s refusal is returned as is.
+    #[must_use]
+    fn first_refusal<B: Approver>(self, other: B) -> FirstRefusal<Self, B> {
+        FirstRefusal(self, other)
+    }
+}
+
+impl<T: Approver + Sized> ApproverExt for T {}
+
+/// An `Approver` that approves only if both inner `Approver`s do.
+/// See `ApproverExt::and`
+#[derive(Debug, Clone, Copy)]
+pub struct And<A, B>(A, B);
+
+impl<A: Approver, B: Approver> Approver for And<A, B> {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        let a = self.0.is_client_allowed(s);
+        let b = self.1.is_client_allowed(s);
+        if severity(a) >= severity(b) { a } else { b }
+    }
+}
+
+/// An `Approver` that approves if either inner `Approver` does.
+/// See `ApproverExt::or`
+#[derive(Debug, Clone, Copy)]
+pub struct Or<A, B>(A, B);
+
+impl<A: Approver, B: Approver> Approver for Or<A, B> {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        let a = self.0.is_client_allowed(s);
+        if a == Approval::Approve {
+            return a;
+        }
+        let b = self.1.is_client_allowed(s);
+        if severity(a) <= severity(b) { a } else { b }
+    }
+}
+
+/// An `Approver` that returns the first refusal of its inner `Approver`s.
+/// See `ApproverExt::first_refusal`
+#[derive(Debug, Clone, Copy)]
+pub struct FirstRefusal<A, B>(A, B);
+
+impl<A: Approver, B: Approver> Approver for FirstRefusal<A, B> {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        match self.0.is_client_allowed(s) {
+            Approval::Approve => self.1.is_client_allowed(s),
+            refusal => refusal,
+        }
+    }
+}
+
+/// An `Approver` made from a closure, for example a maintenance mode switch:
+///
+/// ```
+/// use mosaic_net::{Approval, Approver, FnApprover};
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicBool, Ordering};
+///
+/// let maintenance = Arc::new(AtomicBool::new(false));
+/// let m = maintenance.clone();
+/// let approver = FnApprover::new(move |_| {
+///     if m.load(Ordering::Relaxed) { Approval::Refuse } else { Approval::Approve }
+/// });
+///
+/// let client = "192.0.2.1:4433".parse().unwrap();
+/// assert_eq!(approver.is_client_allowed(client), Approval::Approve);
+/// maintenance.store(true, Ordering::Relaxed);
+/// assert_eq!(approver.is_client_allowed(client), Approval::Refuse);
+/// ```
+#[derive(Clone, Copy)]
+pub struct FnApprover<F>(F);
+
+impl<F> std::fmt::Debug for FnApprover<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FnApprover")
+    }
+}
+
+impl<F: Fn(SocketAddr) -> Approval + Send + Sync> FnApprover<F> {
+    /// Create an `Approver` from a closure
+    #[must_use]
+    pub fn new(f: F) -> FnApprover<F> {
+        FnApprover(f)
+    }
+}
+
+impl<F: Fn(SocketAddr) -> Approval + Send + Sync> Approver for FnApprover<F> {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        (self.0)(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const ALL: [Approval; 3] = [
+        Approval::Approve,
+        Approval::Refuse,
+        Approval::SilentlyRefuse,
+    ];
+
+    fn client() -> SocketAddr {
+        "192.0.2.1:4433".parse().unwrap()
+    }
+
+    fn always(approval: Approval) -> impl Approver + Copy {
+        FnApprover::new(move |_| approval)
+    }
+
+    // An approver that counts how often it is consulted
+    fn counting(approval: Approval, count: &Arc<AtomicUsize>) -> impl Approver + use<> {
+        let count = count.clone();
+        FnApprover::new(move |_| {
+            let _ = count.fetch_add(1, Ordering::Relaxed);
+            approval
+        })
+    }
+
+    #[test]
+    fn and_returns_the_strictest() {
+        for a in ALL {
+            for b in ALL {
+                let expected = if severity(a) >= severity(b) { a } else { b };
+                let approval = always(a).and(always(b)).is_client_allowed(client());
+                assert_eq!(approval, expected, "{a:?} and {b:?}");
+            }
+        }
+
+        // Both are consulted even when the first refuses
+        let count = Arc::new(AtomicUsize::new(0));
+        let approver = always(Approval::Refuse).and(counting(Approval::Approve, &count));
+        assert_eq!(approver.is_client_allowed(client()), Approval::Refuse);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn or_returns_the_most_lenient() {
+        for a in ALL {
+            for b in ALL {
+                let expected = if severity(a) <= severity(b) { a } else { b };
+                let approval = always(a).or(always(b)).is_client_allowed(client());
+                assert_eq!(approval, expected, "{a:?} or {b:?}");
+            }
+        }
+
+        // The second is not consulted if the first approves
+        let count = Arc::new(AtomicUsize::new(0));
+        let approver = always(Approval::Approve).or(counting(Approval::Refuse, &count));
+        assert_eq!(approver.is_client_allowed(client()), Approval::Approve);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn first_refusal_returns_the_first_refusal() {
+        for a in ALL {
+            for b in ALL {
+                let expected = if a == Approval::Approve { b } else { a };
+                let approval = always(a)
+                    .first_refusal(always(b))
+                    .is_client_allowed(client());
+                assert_eq!(approval, expected, "{a:?} then {b:?}");
+            }
+        }
+
+        // The second is not consulted if the first refuses
+        let count = Arc::new(AtomicUsize::new(0));
+        let approver = always(Approval::Refuse).first_refusal(counting(Approval::Approve, &count));
+        assert_eq!(approver.is_client_allowed(client()), Approval::Refuse);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn chain_stops_at_the_first_refusal() {
+        assert_eq!(
+            ApproverChain::new().is_client_allowed(client()),
+            Approval::Approve
+        );
+
+        let first = Arc::new(AtomicUsize::new(0));
+        let last = Arc::new(AtomicUsize::new(0));
+        let mut chain = ApproverChain::new();
+        chain.push(counting(Approval::Approve, &first));
+        chain.push(always(Approval::SilentlyRefuse));
+        chain.push(always(Approval::Refuse));
+        chain.push(counting(Approval::Approve, &last));
+        assert_eq!(chain.is_client_allowed(client()), Approval::SilentlyRefuse);
+        assert_eq!(first.load(Ordering::Relaxed), 1);
+        assert_eq!(last.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn shared_approvers_approve_alike() {
+        let shared: Arc<dyn Approver> = Arc::new(always(Approval::Refuse));
+        let approver = shared.clone().or(always(Approval::SilentlyRefuse));
+        assert_eq!(approver.is_client_allowed(client()), Approval::Refuse);
+        assert_eq!(shared.is_client_allowed(client()), Approval::Refuse);
+    }
+}
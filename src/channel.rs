@@ -1,94 +1,598 @@
+use crate::close_code::CloseCode;
 use crate::error::{Error, InnerError};
+use crate::stats::ChannelGuard;
+use futures_core::Stream;
+use futures_core::stream::FusedStream;
+use futures_sink::Sink;
 use mosaic_core::Message;
-use quinn::{RecvStream, SendStream};
+use quinn::{Chunk, ReadError, RecvStream, SendStream};
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// The default maximum size of a single incoming `Message` (1 MiB)
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Options that govern the behavior of each `Channel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelOptions {
+    /// The maximum size of an incoming `Message`, including its 8 byte header.
+    /// Larger messages are rejected before their body is read.
+    pub max_message_size: usize,
+
+    /// Once the first byte of an incoming `Message` arrives, the rest of it must
+    /// arrive within this long. `None` means no limit.
+    pub message_timeout: Option<Duration>,
+
+    /// If no incoming `Message` is started within this long of the `Channel`
+    /// opening or of the previous `Message` completing, the `Channel` is
+    /// considered idle. `None` means no limit.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ChannelOptions {
+    fn default() -> ChannelOptions {
+        ChannelOptions {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            message_timeout: None,
+            idle_timeout: None,
+        }
+    }
+}
 
 /// Bidirectional stream
+///
+/// Use `split` to get independently owned `ChannelSender` and `ChannelReceiver`
+/// halves, for example to read in one task while writing from another.
+///
+/// A `Channel` is also a `Stream` of incoming `Message`s and a `Sink` for
+/// outgoing ones. The `Stream` ends after the first error other than a
+/// `Message` that failed to parse.
 #[derive(Debug)]
 pub struct Channel {
-    send: SendStream,
-    recv: RecvStream,
-    partial: Vec<u8>,
-    bytes_read: usize,
+    sender: ChannelSender,
+    receiver: ChannelReceiver,
 }
 
 impl Channel {
     /// Create a new `Channel` from streams
-    pub(crate) fn new(send: SendStream, recv: RecvStream) -> Channel {
+    pub(crate) fn new(
+        send: SendStream,
+        recv: RecvStream,
+        options: ChannelOptions,
+        guard: ChannelGuard,
+    ) -> Channel {
+        let guard = Arc::new(guard);
         Channel {
-            send,
-            recv,
-            partial: vec![0; 8],
-            bytes_read: 0,
+            sender: ChannelSender {
+                send,
+                pending: Vec::new(),
+                written: 0,
+                guard: guard.clone(),
+            },
+            receiver: ChannelReceiver {
+                recv,
+                partial: Vec::with_capacity(8),
+                options,
+                last_activity: Instant::now(),
+                message_started: Instant::now(),
+                sleep: None,
+                terminated: false,
+                guard,
+            },
+        }
+    }
+
+    /// Split into independently owned sending and receiving halves
+    ///
+    /// Any partially received `Message` stays with the `ChannelReceiver`.
+    #[must_use]
+    pub fn split(self) -> (ChannelSender, ChannelReceiver) {
+        (self.sender, self.receiver)
+    }
+
+    /// Put back together the halves created by `split`
+    ///
+    /// # Errors
+    ///
+    /// Gives the halves back if they did not come from the same `Channel`
+    #[allow(clippy::result_large_err)]
+    pub fn reunite(
+        sender: ChannelSender,
+        receiver: ChannelReceiver,
+    ) -> Result<Channel, (ChannelSender, ChannelReceiver)> {
+        // Stream IDs are only unique within a connection, so compare the
+        // halves' shared guard instead
+        if Arc::ptr_eq(&sender.guard, &receiver.guard) {
+            Ok(Channel { sender, receiver })
+        } else {
+            Err((sender, receiver))
         }
     }
 
+    /// Get the maximum size of an incoming `Message`
+    #[must_use]
+    pub fn max_message_size(&self) -> usize {
+        self.receiver.max_message_size()
+    }
+
+    /// Set the maximum size of an incoming `Message` on this `Channel`
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.receiver.set_max_message_size(max_message_size);
+    }
+
+    /// Get the `ChannelOptions` in effect
+    #[must_use]
+    pub fn options(&self) -> ChannelOptions {
+        self.receiver.options()
+    }
+
+    /// Change the `ChannelOptions` in effect
+    pub fn set_options(&mut self, options: ChannelOptions) {
+        self.receiver.set_options(options);
+    }
+
     /// Send a `Message`
     ///
+    /// See `ChannelSender::send`
+    ///
     /// # Errors
     ///
-    /// Returns an Err only if there was a QUIC writing problem
+    /// Returns an Err only if there was a QUIC writing problem. If some but not
+    /// all of the bytes were written, this is `InnerError::PartialWrite`.
     pub async fn send(&mut self, message: Message) -> Result<usize, Error> {
-        Ok(self.send.write(message.as_bytes()).await?)
+        self.sender.send(message).await
+    }
+
+    /// Send a batch of `Message`s with a single write
+    ///
+    /// See `ChannelSender::send_many`
+    ///
+    /// # Errors
+    ///
+    /// Returns an Err only if there was a QUIC writing problem. If some but not
+    /// all of the bytes were written, this is `InnerError::PartialWrite`.
+    pub async fn send_many(&mut self, messages: &[Message]) -> Result<usize, Error> {
+        self.sender.send_many(messages).await
+    }
+
+    /// Receive a `Message`
+    ///
+    /// See `ChannelReceiver::recv`. This is cancel-safe.
+    ///
+    /// # Errors
+    ///
+    /// Returns an Err if there was a QUIC reading problem, if the incoming
+    /// Message was invalid, or if the incoming Message was too large.
+    pub async fn recv(&mut self) -> Result<Option<Message>, Error> {
+        self.receiver.recv().await
+    }
+
+    /// Finish this `Channel`. Afterwards you cannot write to it anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already finished.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.sender.finish()
+    }
+
+    /// Abandon sending, telling the peer why. Unsent data is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already finished or reset.
+    pub fn reset(&mut self, code: CloseCode) -> Result<(), Error> {
+        self.sender.reset(code)
+    }
+
+    /// Ask the peer to stop sending, telling them why. Further incoming data
+    /// is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already stopped or fully received.
+    pub fn stop(&mut self, code: CloseCode) -> Result<(), Error> {
+        self.receiver.stop(code)
+    }
+}
+
+impl Stream for Channel {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+    }
+}
+
+impl FusedStream for Channel {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+impl Sink<Message> for Channel {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().sender).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Message) -> Result<(), Error> {
+        Pin::new(&mut self.get_mut().sender).start_send(message)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().sender).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().sender).poll_close(cx)
+    }
+}
+
+/// The sending half of a `Channel`
+///
+/// This is also a `Sink` for outgoing `Message`s.
+#[derive(Debug)]
+pub struct ChannelSender {
+    send: SendStream,
+
+    // Bytes accepted by the `Sink` but not yet written
+    pending: Vec<u8>,
+    written: usize,
+
+    // Counts the `Channel` as open until both halves are dropped, and tells
+    // whether two halves belong together
+    guard: Arc<ChannelGuard>,
+}
+
+impl ChannelSender {
+    /// Send a `Message`
+    ///
+    /// This writes the entire message before returning. It is not cancel-safe:
+    /// if the future is dropped part way through, the peer will see a truncated
+    /// message and the `Channel` should be abandoned.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an Err only if there was a QUIC writing problem. If some but not
+    /// all of the bytes were written, this is `InnerError::PartialWrite`.
+    pub async fn send(&mut self, message: Message) -> Result<usize, Error> {
+        std::future::poll_fn(|cx| self.poll_write_pending(cx)).await?;
+        write_all(&mut self.send, message.as_bytes()).await
+    }
+
+    /// Send a batch of `Message`s with a single write
+    ///
+    /// This has the same guarantees as `send`, but avoids awaiting once per message.
+    ///
+    /// Returns the total number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an Err only if there was a QUIC writing problem. If some but not
+    /// all of the bytes were written, this is `InnerError::PartialWrite`.
+    pub async fn send_many(&mut self, messages: &[Message]) -> Result<usize, Error> {
+        let len = messages.iter().map(|m| m.as_bytes().len()).sum();
+        let mut buffer: Vec<u8> = Vec::with_capacity(len);
+        for message in messages {
+            buffer.extend_from_slice(message.as_bytes());
+        }
+        std::future::poll_fn(|cx| self.poll_write_pending(cx)).await?;
+        write_all(&mut self.send, &buffer).await
+    }
+
+    /// Finish sending. Afterwards you cannot write anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already finished.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.send
+            .finish()
+            .map_err(|_| InnerError::ChannelAlreadyFinished.into())
+    }
+
+    /// Abandon sending, telling the peer why. Unsent data is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already finished or reset.
+    pub fn reset(&mut self, code: CloseCode) -> Result<(), Error> {
+        self.pending.clear();
+        self.written = 0;
+        self.send
+            .reset(code.into())
+            .map_err(|_| InnerError::ChannelAlreadyFinished.into())
+    }
+
+    // Write out anything the `Sink` has accepted but not yet written
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        while self.written < self.pending.len() {
+            let n = match ready!(
+                Pin::new(&mut self.send).poll_write(cx, &self.pending[self.written..])
+            ) {
+                Ok(n) => n,
+                Err(e) => return Poll::Ready(Err(e.into())),
+            };
+            self.written += n;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<Message> for ChannelSender {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_write_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Message) -> Result<(), Error> {
+        self.get_mut().pending.extend_from_slice(message.as_bytes());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_write_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Poll::Ready(this.finish())
+    }
+}
+
+/// The receiving half of a `Channel`
+///
+/// This is also a `Stream` of incoming `Message`s, which ends after the first
+/// error other than a `Message` that failed to parse.
+#[derive(Debug)]
+pub struct ChannelReceiver {
+    recv: RecvStream,
+    partial: Vec<u8>,
+    options: ChannelOptions,
+
+    // For enforcing the idle and message timeouts
+    last_activity: Instant,
+    message_started: Instant,
+    sleep: Option<Pin<Box<Sleep>>>,
+
+    // Set at the end of the stream or on a fatal error, after which nothing
+    // more is read
+    terminated: bool,
+
+    // Counts the `Channel` as open until both halves are dropped, and tells
+    // whether two halves belong together
+    guard: Arc<ChannelGuard>,
+}
+
+impl ChannelReceiver {
+    /// Get the maximum size of an incoming `Message`
+    #[must_use]
+    pub fn max_message_size(&self) -> usize {
+        self.options.max_message_size
+    }
+
+    /// Set the maximum size of an incoming `Message`
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.options.max_message_size = max_message_size;
+    }
+
+    /// Get the `ChannelOptions` in effect
+    #[must_use]
+    pub fn options(&self) -> ChannelOptions {
+        self.options
+    }
+
+    /// Change the `ChannelOptions` in effect
+    pub fn set_options(&mut self, options: ChannelOptions) {
+        self.options = options;
+    }
+
+    /// Ask the peer to stop sending, telling them why. Further incoming data
+    /// is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream was already stopped or fully received.
+    pub fn stop(&mut self, code: CloseCode) -> Result<(), Error> {
+        self.recv
+            .stop(code.into())
+            .map_err(|_| InnerError::ChannelAlreadyFinished.into_err())?;
+        self.terminated = true;
+        Ok(())
     }
 
     /// Receive a `Message`
     ///
     /// This is cancel-safe. It remembers partial reads and picks up where it left off.
     ///
+    /// The receive buffer only grows as bytes actually arrive, so a peer cannot
+    /// make us commit memory merely by announcing a large message.
+    ///
     /// # Errors
     ///
-    /// Returns an Err if there was a QUIC reading problem or if the incoming
-    /// Message was invalid
+    /// Returns an Err if there was a QUIC reading problem, if the incoming
+    /// Message was invalid, or if the incoming Message was larger than
+    /// `max_message_size`, in which case the stream is also stopped with
+    /// `CloseCode::MessageTooLarge`.
+    ///
+    /// If the `message_timeout` or `idle_timeout` run out, this returns
+    /// `InnerError::MessageTimeout` or `InnerError::ChannelIdleTimeout`
+    /// respectively, and the stream is stopped with `CloseCode::Timeout`.
+    ///
+    /// Once the stream has ended, been stopped, or had any error other than a
+    /// `Message` that failed to parse, this returns `Ok(None)`.
     pub async fn recv(&mut self) -> Result<Option<Message>, Error> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Poll to receive a `Message`
+    ///
+    /// This is the polling form of `recv` and has the same guarantees.
+    ///
+    /// # Errors
+    ///
+    /// Returns an Err if there was a QUIC reading problem, if the incoming
+    /// Message was invalid, if the incoming Message was too large, or if
+    /// a timeout ran out.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Message>, Error>> {
+        if self.terminated {
+            return Poll::Ready(Ok(None));
+        }
+
+        match self.poll_read_message(cx) {
+            // A Message that fails to parse leaves the stream in step
+            Poll::Ready(Ok(Some(bytes))) => {
+                return Poll::Ready(Message::from_bytes(bytes).map(Some).map_err(Into::into));
+            }
+            Poll::Ready(result) => {
+                self.terminated = true;
+                return Poll::Ready(result.map(|_| None));
+            }
+            Poll::Pending => {}
+        }
+
+        // Nothing more can be read right now, so check if the peer ran out of time
+        if let Poll::Ready(inner) = self.poll_deadline(cx) {
+            let _ = self.recv.stop(CloseCode::Timeout.into());
+            self.terminated = true;
+            return Poll::Ready(Err(inner.into()));
+        }
+
+        Poll::Pending
+    }
+
+    // Read the bytes of the next Message. Any error is fatal to the stream.
+    fn poll_read_message(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Vec<u8>>, Error>> {
         // Get the first 8 bytes
-        while self.bytes_read < 8 {
-            let Some(n) = self
-                .recv
-                .read(&mut self.partial[self.bytes_read..8])
-                .await?
-            else {
-                return Ok(None);
-            };
-            self.bytes_read += n;
-            if self.bytes_read >= 8 {
-                break;
+        while self.partial.len() < 8 {
+            match ready!(self.poll_chunk(cx, 8 - self.partial.len())) {
+                Ok(Some(chunk)) => self.take_chunk(&chunk),
+                Ok(None) => return Poll::Ready(Ok(None)),
+                Err(e) => return Poll::Ready(Err(e.into())),
             }
         }
 
         // Extract the message length (32-bit little endian at bytes 4..8)
-        let message_len = u32::from_le_bytes(self.partial[4..8].try_into().unwrap()) as usize;
+        let message_len = u32::from_le_bytes([
+            self.partial[4],
+            self.partial[5],
+            self.partial[6],
+            self.partial[7],
+        ]) as usize;
         if message_len < 8 {
-            return Err(
-                InnerError::General(format!("invalid message length: {message_len}")).into(),
-            );
+            return Poll::Ready(Err(InnerError::General(format!(
+                "invalid message length: {message_len}"
+            ))
+            .into()));
+        }
+        if message_len > self.options.max_message_size {
+            let _ = self.recv.stop(CloseCode::MessageTooLarge.into());
+            return Poll::Ready(Err(InnerError::MessageTooLarge(
+                message_len,
+                self.options.max_message_size,
+            )
+            .into()));
         }
-        self.partial.resize(message_len, 0);
 
-        // Read the remaining bytes
-        while self.bytes_read < message_len {
-            let Some(n) = self.recv.read(&mut self.partial[self.bytes_read..]).await? else {
-                return Ok(None);
-            };
-            self.bytes_read += n;
-            if self.bytes_read >= message_len {
-                break;
+        // Read the remaining bytes, growing the buffer only as they arrive
+        while self.partial.len() < message_len {
+            match ready!(self.poll_chunk(cx, message_len - self.partial.len())) {
+                Ok(Some(chunk)) => self.take_chunk(&chunk),
+                Ok(None) => return Poll::Ready(Ok(None)),
+                Err(e) => return Poll::Ready(Err(e.into())),
             }
         }
 
-        let taken = std::mem::replace(&mut self.partial, vec![0; 8]);
-        self.bytes_read = 0;
+        let taken = std::mem::replace(&mut self.partial, Vec::with_capacity(8));
+        self.last_activity = Instant::now();
 
-        Ok(Some(Message::from_bytes(taken)?))
+        Poll::Ready(Ok(Some(taken)))
     }
 
-    /// Finish this `Channel`. Afterwards you cannot write to it anymore.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the stream was already finished.
-    pub fn finish(&mut self) -> Result<(), Error> {
-        self.send
-            .finish()
-            .map_err(|_| InnerError::ChannelAlreadyFinished.into())
+    fn take_chunk(&mut self, chunk: &Chunk) {
+        if self.partial.is_empty() && !chunk.bytes.is_empty() {
+            self.message_started = Instant::now();
+        }
+        self.partial.extend_from_slice(&chunk.bytes);
+    }
+
+    // Wait for whichever timeout currently applies: the message timeout if we
+    // are part way through a message, the idle timeout otherwise
+    fn poll_deadline(&mut self, cx: &mut Context<'_>) -> Poll<InnerError> {
+        let (deadline, inner) = if self.partial.is_empty() {
+            (
+                self.options.idle_timeout.map(|d| self.last_activity + d),
+                InnerError::ChannelIdleTimeout,
+            )
+        } else {
+            (
+                self.options
+                    .message_timeout
+                    .map(|d| self.message_started + d),
+                InnerError::MessageTimeout,
+            )
+        };
+        let Some(deadline) = deadline else {
+            return Poll::Pending;
+        };
+
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if sleep.deadline() != deadline {
+            sleep.as_mut().reset(deadline);
+        }
+        ready!(sleep.as_mut().poll(cx));
+        Poll::Ready(inner)
+    }
+
+    // Reading a chunk is cancel-safe, so it is fine to drop this future
+    // whenever it is pending
+    fn poll_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max_length: usize,
+    ) -> Poll<Result<Option<Chunk>, ReadError>> {
+        pin!(self.recv.read_chunk(max_length, true)).poll(cx)
+    }
+}
+
+impl Stream for ChannelReceiver {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx).map(Result::transpose)
+    }
+}
+
+impl FusedStream for ChannelReceiver {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+// Write all of `bytes`, keeping count so that a failure part way through is
+// reported as such
+async fn write_all(send: &mut SendStream, bytes: &[u8]) -> Result<usize, Error> {
+    let mut written: usize = 0;
+    while written < bytes.len() {
+        match send.write(&bytes[written..]).await {
+            Ok(n) => written += n,
+            Err(e) if written == 0 => return Err(e.into()),
+            Err(e) => return Err(InnerError::PartialWrite(written, Box::new(e)).into()),
+        }
     }
+    Ok(written)
 }
@@ -0,0 +1,386 @@
+use crate::error::{Error, InnerError};
+use crate::server::{Approval, Approver};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// An IPv4 or IPv6 address prefix, such as `192.168.0.0/16` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Create a `Cidr` from an address and prefix length. Bits of the address
+    /// beyond the prefix are cleared.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `prefix_len` is longer than the address
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Cidr, Error> {
+        let addr = addr.to_canonical();
+        let addr = match addr {
+            IpAddr::V4(a) if prefix_len <= 32 => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(a) & v4_mask(prefix_len)))
+            }
+            IpAddr::V6(a) if prefix_len <= 128 => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(a) & v6_mask(prefix_len)))
+            }
+            _ => return Err(InnerError::InvalidCidr(format!("{addr}/{prefix_len}")).into()),
+        };
+        Ok(Cidr { addr, prefix_len })
+    }
+
+    /// The (masked) network address
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length in bits
+    #[must_use]
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `ip` falls within this prefix. IPv4-mapped IPv6 addresses are
+    /// treated as IPv4.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & v4_mask(self.prefix_len) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & v6_mask(self.prefix_len) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn v4_mask(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+fn v6_mask(prefix_len: u8) -> u128 {
+    u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    /// Parse `address/prefix_len`, or a bare address meaning just that address
+    fn from_str(s: &str) -> Result<Cidr, Error> {
+        let invalid = || InnerError::InvalidCidr(s.to_owned()).into_err();
+        let (addr, prefix_len) = if let Some((addr, len)) = s.split_once('/') {
+            let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+            let len: u8 = len.parse().map_err(|_| invalid())?;
+            (addr, len)
+        } else {
+            let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+            let len = if addr.to_canonical().is_ipv4() {
+                32
+            } else {
+                128
+            };
+            (addr, len)
+        };
+        Cidr::new(addr, prefix_len)
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// A set of address prefix rules used by a `CidrApprover`
+///
+/// The most specific (longest) matching prefix decides. If an allow rule and
+/// a refuse rule have the same prefix, the refusal wins. Addresses that match
+/// no rule get the default.
+///
+/// Rules can be parsed from text, one per line:
+///
+/// ```text
+/// # Comments and blank lines are ignored
+/// default refuse
+/// allow 10.0.0.0/8
+/// allow 2001:db8::/32
+/// refuse 10.1.0.0/16
+/// silently-refuse 203.0.113.7
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CidrRules {
+    rules: Vec<(Cidr, Approval)>,
+    default: Approval,
+}
+
+impl Default for CidrRules {
+    fn default() -> CidrRules {
+        CidrRules::new(Approval::Approve)
+    }
+}
+
+impl CidrRules {
+    /// Create an empty set of rules, with the `default` used when no rule matches
+    #[must_use]
+    pub fn new(default: Approval) -> CidrRules {
+        CidrRules {
+            rules: Vec::new(),
+            default,
+        }
+    }
+
+    /// Add a rule that `cidr` gets `approval`
+    pub fn add(&mut self, cidr: Cidr, approval: Approval) {
+        self.rules.push((cidr, approval));
+    }
+
+    /// Add a rule allowing `cidr`
+    pub fn allow(&mut self, cidr: Cidr) {
+        self.add(cidr, Approval::Approve);
+    }
+
+    /// Add a rule refusing `cidr`
+    pub fn refuse(&mut self, cidr: Cidr) {
+        self.add(cidr, Approval::Refuse);
+    }
+
+    /// Add a rule silently refusing `cidr`
+    pub fn silently_refuse(&mut self, cidr: Cidr) {
+        self.add(cidr, Approval::SilentlyRefuse);
+    }
+
+    /// The approval used when no rule matches
+    #[must_use]
+    pub fn default_approval(&self) -> Approval {
+        self.default
+    }
+
+    /// Set the approval used when no rule matches
+    pub fn set_default_approval(&mut self, default: Approval) {
+        self.default = default;
+    }
+
+    /// Decide on `ip`
+    #[must_use]
+    pub fn approval_for(&self, ip: IpAddr) -> Approval {
+        let mut best: Option<(u8, Approval)> = None;
+        for (cidr, approval) in &self.rules {
+            if !cidr.contains(ip) {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some((len, current)) => {
+                    cidr.prefix_len() > len
+                        || (cidr.prefix_len() == len && current == Approval::Approve)
+                }
+            };
+            if better {
+                best = Some((cidr.prefix_len(), *approval));
+            }
+        }
+        best.map_or(self.default, |(_, approval)| approval)
+    }
+
+    /// Load rules from a text file
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or contains an invalid line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CidrRules, Error> {
+        std::fs::read_to_string(path)?.parse()
+    }
+}
+
+impl FromStr for CidrRules {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CidrRules, Error> {
+        let mut rules = CidrRules::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || InnerError::InvalidCidrRule(i + 1, line.to_owned()).into_err();
+            let mut words = line.split_whitespace();
+            let (Some(action), Some(arg), None) = (words.next(), words.next(), words.next()) else {
+                return Err(invalid());
+            };
+            let approval_of = |word: &str| match word {
+                "allow" => Ok(Approval::Approve),
+                "refuse" => Ok(Approval::Refuse),
+                "silently-refuse" => Ok(Approval::SilentlyRefuse),
+                _ => Err(invalid()),
+            };
+            if action == "default" {
+                rules.default = approval_of(arg)?;
+            } else {
+                let approval = approval_of(action)?;
+                let cidr: Cidr = arg.parse().map_err(|_| invalid())?;
+                rules.add(cidr, approval);
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// An `Approver` that decides based on IPv4 and IPv6 address prefix rules.
+///
+/// The rules can be replaced atomically while the server runs, for example
+/// after editing the rules file.
+#[derive(Debug)]
+pub struct CidrApprover {
+    rules: RwLock<Arc<CidrRules>>,
+}
+
+impl CidrApprover {
+    /// Create a `CidrApprover` from rules
+    #[must_use]
+    pub fn new(rules: CidrRules) -> CidrApprover {
+        CidrApprover {
+            rules: RwLock::new(Arc::new(rules)),
+        }
+    }
+
+    /// Create a `CidrApprover` from a rules file
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or contains an invalid line
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<CidrApprover, Error> {
+        Ok(CidrApprover::new(CidrRules::load(path)?))
+    }
+
+    /// Get the rules currently in effect
+    #[must_use]
+    pub fn rules(&self) -> Arc<CidrRules> {
+        self.rules
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replace the rules
+    pub fn reload(&self, rules: CidrRules) {
+        *self.rules.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(rules);
+    }
+
+    /// Replace the rules from a rules file. If the file is invalid, the
+    /// current rules stay in effect.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or contains an invalid line
+    pub fn reload_from_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.reload(CidrRules::load(path)?);
+        Ok(())
+    }
+}
+
+impl Approver for CidrApprover {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        self.rules().approval_for(s.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_masks_host_bits() {
+        let cidr: Cidr = "192.168.37.5/16".parse().unwrap();
+        assert_eq!(cidr.addr(), ip("192.168.0.0"));
+        assert_eq!(cidr.to_string(), "192.168.0.0/16");
+
+        let cidr: Cidr = "2001:db8:ffff::1/32".parse().unwrap();
+        assert_eq!(cidr.addr(), ip("2001:db8::"));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.7")));
+        assert!(!any.contains(ip("2001:db8::1")));
+
+        let host: Cidr = "203.0.113.7".parse().unwrap();
+        assert_eq!(host.prefix_len(), 32);
+        assert!(host.contains(ip("203.0.113.7")));
+        assert!(!host.contains(ip("203.0.113.8")));
+    }
+
+    #[test]
+    fn cidr_rejects_invalid() {
+        for s in [
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "nonsense",
+        ] {
+            assert!(s.parse::<Cidr>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn cidr_treats_ipv4_mapped_as_ipv4() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains(ip("::ffff:10.1.2.3")));
+        assert!(!cidr.contains(ip("::ffff:11.1.2.3")));
+
+        let mapped: Cidr = "::ffff:10.1.2.3".parse().unwrap();
+        assert_eq!(mapped, "10.1.2.3/32".parse().unwrap());
+    }
+
+    #[test]
+    fn longest_prefix_wins_and_refusal_breaks_ties() {
+        let rules: CidrRules = "
+            # office network, except one subnet
+            default refuse
+            allow 10.0.0.0/8
+            refuse 10.1.0.0/16
+            allow 10.1.2.0/24
+            allow 203.0.113.0/24
+            silently-refuse 203.0.113.0/24
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(rules.default_approval(), Approval::Refuse);
+        assert_eq!(rules.approval_for(ip("10.9.9.9")), Approval::Approve);
+        assert_eq!(rules.approval_for(ip("10.1.9.9")), Approval::Refuse);
+        assert_eq!(rules.approval_for(ip("10.1.2.9")), Approval::Approve);
+        assert_eq!(
+            rules.approval_for(ip("203.0.113.7")),
+            Approval::SilentlyRefuse
+        );
+        assert_eq!(rules.approval_for(ip("192.0.2.1")), Approval::Refuse);
+        assert_eq!(rules.approval_for(ip("::ffff:10.9.9.9")), Approval::Approve);
+    }
+
+    #[test]
+    fn rules_reject_invalid_lines() {
+        for s in [
+            "allow",
+            "allow 10.0.0.0/8 extra",
+            "permit 10.0.0.0/8",
+            "allow 10.0.0.0/40",
+            "default maybe",
+        ] {
+            assert!(s.parse::<CidrRules>().is_err(), "{s}");
+        }
+    }
+}
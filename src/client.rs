@@ -1,20 +1,68 @@
 use crate::ALPN_QUIC_MOSAIC;
-use crate::channel::Channel;
-use crate::error::Error;
-use mosaic_core::{PublicKey, SecretKey};
-use quinn::ClientConfig as QuinnClientConfig;
+use crate::channel::{Channel, ChannelOptions};
+use crate::close_code::CloseCode;
+use crate::error::{Error, InnerError};
+use crate::identity::{AcceptedKeysVerifier, peer_public_key, server_name};
+use crate::known_servers::KnownServers;
+use crate::locator::ServerLocator;
+use crate::resolver::{Resolver, SystemResolver, split_host_port};
+use crate::session::{EarlyData, SessionStore};
+use crate::stats::{ChannelCounter, ConnectionStats};
+use crate::transport::TransportOptions;
+use mosaic_core::{Message, MessageType, PublicKey, SecretKey};
+use quinn::{ClientConfig as QuinnClientConfig, TransportConfig};
 use rustls::ClientConfig as TlsClientConfig;
-use std::net::SocketAddr;
+use rustls::client::Resumption;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// The default delay between starting connection attempts to successive
+/// candidate addresses
+pub const DEFAULT_CONNECT_STAGGER: Duration = Duration::from_millis(250);
+
+/// The default overall deadline for connecting
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// This configuration is used to produce a `Client`
 #[derive(Debug)]
 pub struct ClientConfig {
-    #[allow(dead_code)]
-    server_public_key: PublicKey,
-    server_socket: SocketAddr,
+    server_public_keys: Vec<PublicKey>,
+    server_sockets: Vec<SocketAddr>,
+    server_host: Option<(String, u16)>,
     client_secret_key: Option<SecretKey>,
-    quinn: QuinnClientConfig,
+    tls: Arc<TlsClientConfig>,
+
+    /// Options applied to every `Channel` of the resulting `Client`
+    pub channel_options: ChannelOptions,
+
+    /// Tuning for the QUIC transport
+    pub transport: TransportOptions,
+
+    /// Where to keep TLS session tickets for resumption. `None` (the default)
+    /// means every connection does a full handshake. Tickets do not survive
+    /// a restart of the process.
+    pub session_store: Option<SessionStore>,
+
+    /// Whether to offer 0-RTT early data when resuming a session. This only
+    /// has an effect with a `session_store`, and only matters for
+    /// `ClientConfig::client_with_early_data`.
+    pub early_data: bool,
+
+    /// How long to wait for a connection attempt before also trying the next
+    /// candidate address
+    pub connect_stagger: Duration,
+
+    /// How long to keep trying to connect before giving up. `None` means
+    /// until every candidate address has failed.
+    pub connect_timeout: Option<Duration>,
+
+    /// Resolves the server's host name, if it was given one
+    pub resolver: Arc<dyn Resolver>,
+
+    known_servers: Option<(String, Arc<dyn KnownServers>)>,
 }
 
 impl ClientConfig {
@@ -32,11 +80,122 @@ impl ClientConfig {
         server_socket: SocketAddr,
         client_secret_key: Option<SecretKey>,
     ) -> Result<ClientConfig, Error> {
-        let verifier = Arc::new(alt_tls::SelfSignedCertificateVerifier::new(
-            alt_tls::SUPPORTED_ALGORITHMS,
-            vec![rustls::SignatureScheme::ED25519],
-            Some(server_public_key.as_bytes().to_vec()),
-        ));
+        ClientConfig::with_server_sockets(server_public_key, vec![server_socket], client_secret_key)
+    }
+
+    /// Create a `ClientConfig` for a server reachable at any of several
+    /// candidate addresses, such as its IPv6 and IPv4 addresses.
+    ///
+    /// Connecting races the candidates, starting with the first and
+    /// alternating between address families, staggered by `connect_stagger`.
+    /// The first to complete a handshake wins.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `server_sockets` is empty, and on numerous things that should
+    /// not occur based on input, but might occur as software changes over time.
+    pub fn with_server_sockets(
+        server_public_key: PublicKey,
+        server_sockets: Vec<SocketAddr>,
+        client_secret_key: Option<SecretKey>,
+    ) -> Result<ClientConfig, Error> {
+        if server_sockets.is_empty() {
+            return Err(InnerError::NoServerAddress.into());
+        }
+        ClientConfig::build(
+            vec![server_public_key],
+            server_sockets,
+            None,
+            client_secret_key,
+        )
+    }
+
+    /// Create a `ClientConfig` for a server at `host_port`, such as
+    /// `relay.example.com:443` or `[2001:db8::1]:443`. See also
+    /// `ClientConfig::from_locator`.
+    ///
+    /// The host name is resolved by the `resolver` each time a `Client` is
+    /// created, and every address it resolves to is a candidate. Trust still
+    /// comes only from `server_public_key`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `host_port` is not a valid `host:port`, and on numerous things
+    /// that should not occur based on input, but might occur as software
+    /// changes over time.
+    pub fn with_host(
+        server_public_key: PublicKey,
+        host_port: &str,
+        client_secret_key: Option<SecretKey>,
+    ) -> Result<ClientConfig, Error> {
+        let (server_sockets, server_host) = parse_host_port(host_port)?;
+        ClientConfig::build(
+            vec![server_public_key],
+            server_sockets,
+            server_host,
+            client_secret_key,
+        )
+    }
+
+    /// Create a `ClientConfig` for a server at `host_port` whose key is not
+    /// known in advance (trust on first use).
+    ///
+    /// The first time, any key the server presents is accepted and recorded
+    /// in `known_servers` under `host_port`. From then on, a `Client` is only
+    /// created if the server presents that same key; otherwise connecting
+    /// fails with a `ServerKeyMismatch` error.
+    ///
+    /// This is weaker than knowing the key in advance, as whoever answers the
+    /// first time is trusted.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `host_port` is not a valid `host:port`, and on numerous things
+    /// that should not occur based on input, but might occur as software
+    /// changes over time.
+    pub fn trust_on_first_use(
+        host_port: &str,
+        known_servers: Arc<dyn KnownServers>,
+        client_secret_key: Option<SecretKey>,
+    ) -> Result<ClientConfig, Error> {
+        let (server_sockets, server_host) = parse_host_port(host_port)?;
+        let mut config =
+            ClientConfig::build(Vec::new(), server_sockets, server_host, client_secret_key)?;
+        config.known_servers = Some((host_port.to_ascii_lowercase(), known_servers));
+        Ok(config)
+    }
+
+    /// Create a `ClientConfig` from a `ServerLocator`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the locator names a transport other than `quic`, and on
+    /// numerous things that should not occur based on input, but might occur
+    /// as software changes over time.
+    pub fn from_locator(
+        locator: &ServerLocator,
+        client_secret_key: Option<SecretKey>,
+    ) -> Result<ClientConfig, Error> {
+        if let Some(transport) = locator.transport()
+            && transport != "quic"
+        {
+            return Err(InnerError::UnsupportedTransport(transport.to_owned()).into());
+        }
+        ClientConfig::with_host(
+            locator.public_key(),
+            &locator.host_port(),
+            client_secret_key,
+        )
+    }
+
+    fn build(
+        server_public_keys: Vec<PublicKey>,
+        server_sockets: Vec<SocketAddr>,
+        server_host: Option<(String, u16)>,
+        client_secret_key: Option<SecretKey>,
+    ) -> Result<ClientConfig, Error> {
+        // Replaced by `quinn_config`
+        let verifier = Arc::new(AcceptedKeysVerifier::any());
 
         let rustls_client_config = {
             let builder = TlsClientConfig::builder_with_provider(alt_tls::provider().into())
@@ -62,15 +221,255 @@ impl ClientConfig {
             Arc::new(client_config)
         };
 
-        let quinn_client_config = QuinnClientConfig::new(Arc::new(
-            quinn_proto::crypto::rustls::QuicClientConfig::try_from(rustls_client_config)?,
+        Ok(ClientConfig {
+            server_public_keys,
+            server_sockets,
+            server_host,
+            client_secret_key,
+            tls: rustls_client_config,
+            channel_options: ChannelOptions::default(),
+            transport: TransportOptions::default(),
+            session_store: None,
+            early_data: false,
+            connect_stagger: DEFAULT_CONNECT_STAGGER,
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            resolver: Arc::new(SystemResolver),
+            known_servers: None,
+        })
+    }
+
+    // The QUIC client configuration with our transport and session settings
+    // applied. Sessions are only resumed when we know which key to expect.
+    fn quinn_config(
+        &self,
+        verifier: Arc<AcceptedKeysVerifier>,
+        resumable: bool,
+    ) -> Result<QuinnClientConfig, Error> {
+        let mut rustls_client_config = (*self.tls).clone();
+        rustls_client_config
+            .dangerous()
+            .set_certificate_verifier(verifier);
+        if let Some(store) = &self.session_store
+            && resumable
+        {
+            rustls_client_config.resumption = Resumption::store(store.inner());
+            rustls_client_config.enable_early_data = self.early_data;
+        } else {
+            rustls_client_config.resumption = Resumption::disabled();
+        }
+
+        let mut transport_config = TransportConfig::default();
+        self.transport.apply(&mut transport_config);
+
+        let mut quinn_client_config = QuinnClientConfig::new(Arc::new(
+            quinn_proto::crypto::rustls::QuicClientConfig::try_from(Arc::new(
+                rustls_client_config,
+            ))?,
         ));
+        let _ = quinn_client_config.transport_config(Arc::new(transport_config));
+        Ok(quinn_client_config)
+    }
 
-        Ok(ClientConfig {
-            server_public_key,
+    /// The public keys the server may present, the first being the one it is
+    /// asked for
+    #[must_use]
+    pub fn server_public_keys(&self) -> &[PublicKey] {
+        &self.server_public_keys
+    }
+
+    /// Also accept `server_public_key` from the server, such as the key it is
+    /// rotating to (or from)
+    pub fn add_server_public_key(&mut self, server_public_key: PublicKey) {
+        if !self.server_public_keys.contains(&server_public_key) {
+            self.server_public_keys.push(server_public_key);
+        }
+    }
+
+    /// The candidate addresses of the server, not counting those its host
+    /// name resolves to
+    #[must_use]
+    pub fn server_sockets(&self) -> &[SocketAddr] {
+        &self.server_sockets
+    }
+
+    /// The host name and port of the server, if it was given one
+    #[must_use]
+    pub fn server_host(&self) -> Option<(&str, u16)> {
+        self.server_host.as_ref().map(|(h, p)| (h.as_str(), *p))
+    }
+
+    // The candidate addresses in the order to try them: alternating address
+    // families, starting with the family of the first
+    async fn candidates(&self, deadline: Option<Instant>) -> Result<Vec<SocketAddr>, Error> {
+        let mut all = self.server_sockets.clone();
+        if let Some((host, port)) = &self.server_host {
+            let resolving = self.resolver.resolve(host, *port);
+            let resolved = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, resolving)
+                    .await
+                    .map_err(|_| InnerError::ConnectTimeout.into_err())??,
+                None => resolving.await?,
+            };
+            for addr in resolved {
+                if !all.contains(&addr) {
+                    all.push(addr);
+                }
+            }
+        }
+
+        let Some(first) = all.first().copied() else {
+            return Err(InnerError::NoServerAddress.into());
+        };
+        let (mut preferred, mut other): (Vec<SocketAddr>, Vec<SocketAddr>) = all
+            .into_iter()
+            .partition(|s| s.is_ipv4() == first.is_ipv4());
+        preferred.reverse();
+        other.reverse();
+
+        let mut candidates = Vec::with_capacity(preferred.len() + other.len());
+        loop {
+            match (preferred.pop(), other.pop()) {
+                (None, None) => return Ok(candidates),
+                (a, b) => candidates.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+
+    // When connecting must be done by, if started now
+    fn deadline(&self) -> Option<Instant> {
+        self.connect_timeout.map(|t| Instant::now() + t)
+    }
+
+    // Start connecting to the server at `server_socket` from `endpoint`,
+    // also returning the verifier of the key the server presents
+    fn connecting(
+        &self,
+        endpoint: &quinn::Endpoint,
+        server_socket: SocketAddr,
+    ) -> Result<(quinn::Connecting, Arc<AcceptedKeysVerifier>), Error> {
+        // With trust on first use, any key is accepted the first time, and
+        // only the learned key after that
+        let (expected, verifier) = match self.server_public_keys.first() {
+            Some(key) => (
+                Some(*key),
+                AcceptedKeysVerifier::new(self.server_public_keys.clone()),
+            ),
+            None => match self.known_key()? {
+                Some(known) => (Some(known), AcceptedKeysVerifier::new(vec![known])),
+                None => (None, AcceptedKeysVerifier::any()),
+            },
+        };
+        let verifier = Arc::new(verifier);
+
+        // Our certificate verifier doesn't care about the name. It instead
+        // demands an exact expected key. But session tickets are stored by
+        // name, and servers rotating keys choose which key to present by
+        // name, so the name must be unique to the key we expect.
+        let name = expected.map_or_else(|| "mosaic".to_owned(), server_name);
+        let quinn_config = self.quinn_config(verifier.clone(), expected.is_some())?;
+        let connecting = endpoint.connect_with(quinn_config, server_socket, &name)?;
+        Ok((connecting, verifier))
+    }
+
+    // Start a connection attempt to `server_socket` from `origin`
+    fn attempt(&self, origin: &Origin, server_socket: SocketAddr) -> Result<Attempt, Error> {
+        let (endpoint, owns_endpoint) = origin.endpoint_for(server_socket)?;
+        let started = Instant::now();
+        let (connecting, verifier) = self.connecting(&endpoint, server_socket)?;
+        Ok(Attempt {
             server_socket,
-            client_secret_key,
-            quinn: quinn_client_config,
+            endpoint,
+            owns_endpoint,
+            connecting,
+            verifier,
+            started,
+        })
+    }
+
+    // The error for a failed connection attempt. With trust on first use,
+    // this says so if the server presented a key other than the known one.
+    fn attempt_error(&self, e: quinn::ConnectionError, verifier: &AcceptedKeysVerifier) -> Error {
+        match (&self.known_servers, verifier.refused()) {
+            (Some((server, _)), Some((known, presented))) => {
+                InnerError::ServerKeyMismatch(server.clone(), known, presented).into()
+            }
+            _ => e.into(),
+        }
+    }
+
+    // The key known for the server, with trust on first use
+    fn known_key(&self) -> Result<Option<PublicKey>, Error> {
+        match &self.known_servers {
+            Some((server, known_servers)) => known_servers.known_key(server),
+            None => Ok(None),
+        }
+    }
+
+    // Wrap an established connection
+    async fn wrap(
+        &self,
+        endpoint: quinn::Endpoint,
+        owns_endpoint: bool,
+        connection: quinn::Connection,
+        handshake_duration: Option<Duration>,
+    ) -> Result<Client, Error> {
+        // Before the handshake completes (with early data) nothing has been
+        // presented yet. The verifier only accepts the expected keys, and
+        // `send_early` checks which was presented once the handshake is done.
+        let presented = peer_public_key(&connection);
+        let mut learned_server_key = None;
+        let server_public_key = match (&self.known_servers, self.server_public_keys.first()) {
+            (_, Some(first)) => presented.unwrap_or(*first),
+            (None, None) => return Err(InnerError::General("No server key".to_owned()).into()),
+            (Some((server, known_servers)), None) => {
+                match (known_servers.known_key(server)?, presented) {
+                    (Some(known), Some(presented)) if known != presented => {
+                        connection.close(CloseCode::Unauthorized.into(), b"server key mismatch");
+                        return Err(InnerError::ServerKeyMismatch(
+                            server.clone(),
+                            known,
+                            presented,
+                        )
+                        .into());
+                    }
+                    (Some(known), _) => known,
+                    (None, Some(presented)) => {
+                        // Off the runtime, as the store may write a file
+                        let learned = {
+                            let known_servers = known_servers.clone();
+                            let server = server.clone();
+                            tokio::task::spawn_blocking(move || {
+                                known_servers.learn(&server, presented)
+                            })
+                            .await
+                            .unwrap_or_else(|e| Err(InnerError::General(e.to_string()).into()))
+                        };
+                        if let Err(e) = learned {
+                            connection.close(CloseCode::Unauthorized.into(), b"");
+                            return Err(e);
+                        }
+                        learned_server_key = Some(presented);
+                        presented
+                    }
+                    (None, None) => {
+                        connection.close(CloseCode::Unauthorized.into(), b"no server key");
+                        return Err(InnerError::General("No server key".to_owned()).into());
+                    }
+                }
+            }
+        };
+        Ok(Client {
+            local_endpoint: endpoint,
+            owns_endpoint,
+            remote_socket: connection.remote_address(),
+            connection,
+            server_public_key,
+            learned_server_key,
+            client_secret_key: self.client_secret_key.clone(),
+            channel_options: self.channel_options,
+            channels: ChannelCounter::default(),
+            handshake_duration,
+            established: Instant::now(),
         })
     }
 
@@ -80,34 +479,256 @@ impl ClientConfig {
     /// a wildcard address like `(std::net::Ipv6Addr::UNSPECIFIED, 0).into()` or
     /// `(std::net::Ipv4Addr::UNSPECIFIED, 0).into()`
     ///
+    /// This opens a new UDP socket for the `Client`. To share one socket among
+    /// many `Client`s, use a `ClientEndpoint`.
+    ///
     /// # Errors
     ///
-    /// Errors if the client could not be setup, or the server could not be connected to.
+    /// Errors if the client could not be setup, or the server could not be
+    /// connected to at any candidate address before the `connect_timeout`.
     pub async fn client(&self, local_socket: Option<SocketAddr>) -> Result<Client, Error> {
-        // find out if IPv4 or IPv6
-        let local_socket: SocketAddr = if let Some(lc) = local_socket {
-            lc
-        } else if self.server_socket.is_ipv4() {
-            (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
-        } else {
-            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
-        };
+        self.client_from(&Origin::dedicated(local_socket)?).await
+    }
+
+    pub(crate) async fn client_from(&self, origin: &Origin) -> Result<Client, Error> {
+        let deadline = self.deadline();
+        let candidates = self.candidates(deadline).await?;
+        self.race(origin, deadline, candidates, None).await
+    }
 
-        let mut endpoint = quinn::Endpoint::client(local_socket)?;
-        endpoint.set_default_client_config(self.quinn.clone());
+    /// Create a `Client` like `ClientConfig::client`, and send `messages` on
+    /// a new `Channel` as 0-RTT early data if possible.
+    ///
+    /// Early data can be replayed by an attacker, so only idempotent messages
+    /// (Get and Query) may be sent this way. Anything else, including a
+    /// Subscribe (a replay would open a second subscription), is refused.
+    /// The messages are always delivered exactly once on the returned
+    /// `Channel`; the returned `EarlyData` says whether they went early.
+    ///
+    /// Early data is only offered to the first candidate address. When it is,
+    /// the `connect_timeout` covers sending the messages as well as the
+    /// handshake.
+    ///
+    /// # Errors
+    ///
+    /// Errors if any message is not a Get or a Query, if the client could not
+    /// be setup, the server could not be connected to, or the messages could
+    /// not be sent.
+    pub async fn client_with_early_data(
+        &self,
+        local_socket: Option<SocketAddr>,
+        messages: &[Message],
+    ) -> Result<(Client, Channel, EarlyData), Error> {
+        self.client_with_early_data_from(&Origin::dedicated(local_socket)?, messages)
+            .await
+    }
 
-        // We use a dummy expected hostname. Our certificate verifier doesn't care.
-        // It instead demands an exact expected key.
-        let connecting = endpoint.connect(self.server_socket, "mosaic")?;
+    pub(crate) async fn client_with_early_data_from(
+        &self,
+        origin: &Origin,
+        messages: &[Message],
+    ) -> Result<(Client, Channel, EarlyData), Error> {
+        if !messages
+            .iter()
+            .all(|m| matches!(m.message_type(), MessageType::Get | MessageType::Query))
+        {
+            return Err(InnerError::NotIdempotent.into());
+        }
 
-        let connection = connecting.await?;
-        Ok(Client {
-            local_endpoint: endpoint,
-            remote_socket: self.server_socket,
+        let deadline = self.deadline();
+        let candidates = self.candidates(deadline).await?;
+        let Some(&first) = candidates.first() else {
+            return Err(InnerError::NoServerAddress.into());
+        };
+        let attempt = match self.attempt(origin, first)?.into_0rtt() {
+            Ok(early_attempt) => {
+                let early = self.send_early(early_attempt, messages);
+                return match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline, early)
+                        .await
+                        .map_err(|_| InnerError::ConnectTimeout.into_err())?,
+                    None => early.await,
+                };
+            }
+            Err(attempt) => attempt,
+        };
+
+        // Keep the attempt already underway in the race
+        let client = self
+            .race(origin, deadline, candidates, Some(attempt))
+            .await?;
+        let mut channel = client.new_channel().await?;
+        let _ = channel.send_many(messages).await?;
+        Ok((client, channel, EarlyData::NotAttempted))
+    }
+
+    // Send `messages` as early data on a connection that is still
+    // handshaking, and again after the handshake if the server rejects them
+    async fn send_early(
+        &self,
+        attempt: EarlyAttempt,
+        messages: &[Message],
+    ) -> Result<(Client, Channel, EarlyData), Error> {
+        let EarlyAttempt {
+            endpoint,
+            owns_endpoint,
             connection,
-            server_public_key: self.server_public_key,
-            client_secret_key: self.client_secret_key.clone(),
-        })
+            accepted,
+            verifier,
+            started,
+        } = attempt;
+        let mut client = self.wrap(endpoint, owns_endpoint, connection, None).await?;
+        let mut channel = client.new_channel().await?;
+        let sent = channel.send_many(messages).await;
+        let accepted = accepted.await;
+        client.handshake_duration = Some(started.elapsed());
+
+        // Only now has the server presented its key
+        match peer_public_key(&client.connection) {
+            Some(presented)
+                if self.known_servers.is_some() && presented != client.server_public_key =>
+            {
+                client
+                    .connection
+                    .close(CloseCode::Unauthorized.into(), b"server key mismatch");
+                let server = self.known_servers.as_ref().map(|(s, _)| s.clone());
+                return Err(InnerError::ServerKeyMismatch(
+                    server.unwrap_or_default(),
+                    client.server_public_key,
+                    presented,
+                )
+                .into());
+            }
+            Some(presented) => client.server_public_key = presented,
+            None => {
+                let e = client.connection.closed().await;
+                return Err(self.attempt_error(e, &verifier));
+            }
+        }
+
+        if accepted {
+            let _ = sent?;
+            return Ok((client, channel, EarlyData::Accepted));
+        }
+
+        // The server discarded the early stream; send again
+        let mut channel = client.new_channel().await?;
+        let _ = channel.send_many(messages).await?;
+        Ok((client, channel, EarlyData::Rejected))
+    }
+
+    // Race connection attempts to the candidate addresses, happy eyeballs
+    // style (RFC 8305), including an `underway` attempt if there is one
+    async fn race(
+        &self,
+        origin: &Origin,
+        deadline: Option<Instant>,
+        mut candidates: Vec<SocketAddr>,
+        underway: Option<Attempt>,
+    ) -> Result<Client, Error> {
+        candidates.reverse();
+
+        // Dropping this aborts the losing attempts
+        let mut attempts = JoinSet::new();
+        let mut last_error: Option<Error> = None;
+
+        if let Some(attempt) = underway {
+            candidates.retain(|s| *s != attempt.server_socket);
+            let _ = attempts.spawn(attempt.finish());
+        }
+
+        loop {
+            // Start the next attempt when the previous one has had its chance
+            let start_next = attempts.is_empty() || {
+                let stagger = tokio::time::sleep(self.connect_stagger);
+                let deadline = async {
+                    match deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    Some(joined) = attempts.join_next() => match joined {
+                        Ok(Finished {
+                            endpoint,
+                            owns_endpoint,
+                            result: Ok(connection),
+                            took,
+                            ..
+                        }) => {
+                            return self
+                                .wrap(endpoint, owns_endpoint, connection, Some(took))
+                                .await;
+                        }
+                        Ok(Finished {
+                            result: Err(e),
+                            verifier,
+                            ..
+                        }) => {
+                            last_error = Some(self.attempt_error(e, &verifier));
+                            true
+                        }
+                        Err(e) => {
+                            last_error = Some(InnerError::General(e.to_string()).into());
+                            true
+                        }
+                    },
+                    () = stagger, if !candidates.is_empty() => true,
+                    () = deadline => return Err(InnerError::ConnectTimeout.into()),
+                }
+            };
+
+            if !start_next {
+                continue;
+            }
+            let Some(server_socket) = candidates.pop() else {
+                if attempts.is_empty() {
+                    return Err(last_error.unwrap_or_else(|| InnerError::NoServerAddress.into()));
+                }
+                continue;
+            };
+            match self.attempt(origin, server_socket) {
+                Ok(attempt) => {
+                    let _ = attempts.spawn(attempt.finish());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+    }
+}
+
+// Where connection attempts are made from
+#[derive(Debug)]
+pub(crate) enum Origin {
+    // A new endpoint for each attempt, matching its address family
+    Dedicated,
+
+    // One endpoint for every attempt, and whether the `Client` owns it
+    Fixed(quinn::Endpoint, bool),
+}
+
+impl Origin {
+    // A dedicated endpoint bound to `local_socket`, or per attempt if `None`
+    fn dedicated(local_socket: Option<SocketAddr>) -> Result<Origin, Error> {
+        match local_socket {
+            Some(local_socket) => Ok(Origin::Fixed(quinn::Endpoint::client(local_socket)?, true)),
+            None => Ok(Origin::Dedicated),
+        }
+    }
+
+    // The endpoint to reach `server_socket` from, and whether it is owned
+    fn endpoint_for(&self, server_socket: SocketAddr) -> Result<(quinn::Endpoint, bool), Error> {
+        match self {
+            Origin::Dedicated => {
+                let local_socket: SocketAddr = if server_socket.is_ipv4() {
+                    (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                Ok((quinn::Endpoint::client(local_socket)?, true))
+            }
+            Origin::Fixed(endpoint, owns) => Ok((endpoint.clone(), *owns)),
+        }
     }
 }
 
@@ -118,15 +739,21 @@ impl ClientConfig {
 pub struct Client {
     #[allow(dead_code)]
     local_endpoint: quinn::Endpoint,
+    owns_endpoint: bool,
     #[allow(dead_code)]
     remote_socket: SocketAddr,
     #[allow(dead_code)]
     connection: quinn::Connection,
     #[allow(dead_code)]
     server_public_key: PublicKey,
+    learned_server_key: Option<PublicKey>,
     #[allow(dead_code)]
     #[allow(clippy::struct_field_names)]
     client_secret_key: Option<SecretKey>,
+    channel_options: ChannelOptions,
+    channels: ChannelCounter,
+    handshake_duration: Option<Duration>,
+    established: Instant,
 }
 
 impl Client {
@@ -142,24 +769,48 @@ impl Client {
         &mut self.connection
     }
 
-    /// Get public key of authenticated server
+    /// Get public key of authenticated server. When the `ClientConfig`
+    /// accepted several keys, this is the one the server presented.
     #[must_use]
     pub fn peer(&self) -> PublicKey {
         self.server_public_key
     }
 
-    /// Get remote socket
+    /// The server's key, if this connection was the first use of a trust on
+    /// first use `ClientConfig` and the key was just learned
+    #[must_use]
+    pub fn learned_server_key(&self) -> Option<PublicKey> {
+        self.learned_server_key
+    }
+
+    /// Get remote socket. When the `ClientConfig` had several candidate
+    /// addresses, this is the one that won.
     #[must_use]
     pub fn remote_socket(&self) -> SocketAddr {
         self.remote_socket
     }
 
+    /// Get the options applied to new `Channel`s
+    #[must_use]
+    pub fn channel_options(&self) -> ChannelOptions {
+        self.channel_options
+    }
+
+    /// Set the options applied to new `Channel`s
+    pub fn set_channel_options(&mut self, channel_options: ChannelOptions) {
+        self.channel_options = channel_options;
+    }
+
     /// Close down gracefully.
     ///
     /// `message` will be truncated if it does not fit in a single packet
-    pub async fn close(self, code: u32, reason: &[u8]) {
+    pub async fn close(self, code: CloseCode, reason: &[u8]) {
         self.connection.close(code.into(), reason);
-        self.local_endpoint.wait_idle().await;
+
+        // A shared endpoint lives on for its other connections
+        if self.owns_endpoint {
+            self.local_endpoint.wait_idle().await;
+        }
     }
 
     /// Open a new `Channel`
@@ -169,6 +820,97 @@ impl Client {
     /// Returns an Err if there was a QUIC `open_bi()` problem
     pub async fn new_channel(&self) -> Result<Channel, Error> {
         let (send, recv) = self.connection.open_bi().await?;
-        Ok(Channel::new(send, recv))
+        Ok(Channel::new(
+            send,
+            recv,
+            self.channel_options,
+            self.channels.open(),
+        ))
+    }
+
+    /// A snapshot of the statistics of the connection
+    #[must_use]
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats::new(
+            &self.connection,
+            &self.channels,
+            self.handshake_duration,
+            self.established,
+        )
+    }
+}
+
+// A connection attempt underway
+#[derive(Debug)]
+struct Attempt {
+    server_socket: SocketAddr,
+    endpoint: quinn::Endpoint,
+    // Whether the `Client` would own the endpoint
+    owns_endpoint: bool,
+    connecting: quinn::Connecting,
+    verifier: Arc<AcceptedKeysVerifier>,
+    started: Instant,
+}
+
+// The outcome of an `Attempt`, with what is needed to wrap or explain it
+struct Finished {
+    endpoint: quinn::Endpoint,
+    owns_endpoint: bool,
+    result: Result<quinn::Connection, quinn::ConnectionError>,
+    verifier: Arc<AcceptedKeysVerifier>,
+    took: Duration,
+}
+
+impl Attempt {
+    // Wait for the handshake to finish
+    async fn finish(self) -> Finished {
+        let result = self.connecting.await;
+        Finished {
+            endpoint: self.endpoint,
+            owns_endpoint: self.owns_endpoint,
+            result,
+            verifier: self.verifier,
+            took: self.started.elapsed(),
+        }
+    }
+
+    // Carry on with 0-RTT if there is a session ticket for early data, or
+    // give the attempt back if not (as `quinn::Connecting::into_0rtt` does)
+    #[allow(clippy::result_large_err)]
+    fn into_0rtt(self) -> Result<EarlyAttempt, Attempt> {
+        match self.connecting.into_0rtt() {
+            Ok((connection, accepted)) => Ok(EarlyAttempt {
+                endpoint: self.endpoint,
+                owns_endpoint: self.owns_endpoint,
+                connection,
+                accepted,
+                verifier: self.verifier,
+                started: self.started,
+            }),
+            Err(connecting) => Err(Attempt { connecting, ..self }),
+        }
+    }
+}
+
+// An `Attempt` that can send early data before its handshake is done
+struct EarlyAttempt {
+    endpoint: quinn::Endpoint,
+    owns_endpoint: bool,
+    connection: quinn::Connection,
+    accepted: quinn::ZeroRttAccepted,
+    verifier: Arc<AcceptedKeysVerifier>,
+    started: Instant,
+}
+
+// Candidate addresses, and a host name and port to resolve
+type ServerAddresses = (Vec<SocketAddr>, Option<(String, u16)>);
+
+// Split `host:port` into candidate addresses if the host is an IP address,
+// or a host name to resolve
+fn parse_host_port(host_port: &str) -> Result<ServerAddresses, Error> {
+    let (host, port) = split_host_port(host_port)?;
+    match host.parse::<IpAddr>() {
+        Ok(ip) => Ok((vec![SocketAddr::new(ip, port)], None)),
+        Err(_) => Ok((Vec::new(), Some((host, port)))),
     }
 }
@@ -0,0 +1,151 @@
+use quinn::VarInt;
+
+/// The reason a connection was closed, or a `Channel` was reset or stopped.
+///
+/// These travel as QUIC application error codes, so both sides can tell why
+/// a connection or `Channel` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    /// Normal termination, nothing went wrong
+    Normal,
+
+    /// The peer violated the Mosaic protocol
+    ProtocolViolation,
+
+    /// The peer sent a message larger than we accept
+    MessageTooLarge,
+
+    /// The peer is not authorized
+    Unauthorized,
+
+    /// The server is shutting down
+    ShuttingDown,
+
+    /// The peer exceeded a rate limit
+    RateLimited,
+
+    /// Something went wrong on our side
+    InternalError,
+
+    /// The peer took too long
+    Timeout,
+
+    /// The peer has too many connections open
+    TooManyConnections,
+
+    /// A code we do not recognize
+    Unknown(u64),
+}
+
+impl CloseCode {
+    /// The numeric QUIC application error code
+    #[must_use]
+    pub fn code(self) -> u64 {
+        match self {
+            CloseCode::Normal => 0x00,
+            CloseCode::ProtocolViolation => 0x01,
+            CloseCode::MessageTooLarge => 0x02,
+            CloseCode::Unauthorized => 0x03,
+            CloseCode::ShuttingDown => 0x04,
+            CloseCode::RateLimited => 0x05,
+            CloseCode::InternalError => 0x06,
+            CloseCode::Timeout => 0x07,
+            CloseCode::TooManyConnections => 0x08,
+            CloseCode::Unknown(c) => c,
+        }
+    }
+
+    /// Interpret a numeric QUIC application error code
+    #[must_use]
+    pub fn from_code(code: u64) -> CloseCode {
+        match code {
+            0x00 => CloseCode::Normal,
+            0x01 => CloseCode::ProtocolViolation,
+            0x02 => CloseCode::MessageTooLarge,
+            0x03 => CloseCode::Unauthorized,
+            0x04 => CloseCode::ShuttingDown,
+            0x05 => CloseCode::RateLimited,
+            0x06 => CloseCode::InternalError,
+            0x07 => CloseCode::Timeout,
+            0x08 => CloseCode::TooManyConnections,
+            c => CloseCode::Unknown(c),
+        }
+    }
+}
+
+impl std::fmt::Display for CloseCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseCode::Normal => write!(f, "normal"),
+            CloseCode::ProtocolViolation => write!(f, "protocol violation"),
+            CloseCode::MessageTooLarge => write!(f, "message too large"),
+            CloseCode::Unauthorized => write!(f, "unauthorized"),
+            CloseCode::ShuttingDown => write!(f, "shutting down"),
+            CloseCode::RateLimited => write!(f, "rate limited"),
+            CloseCode::InternalError => write!(f, "internal error"),
+            CloseCode::Timeout => write!(f, "timeout"),
+            CloseCode::TooManyConnections => write!(f, "too many connections"),
+            CloseCode::Unknown(c) => write!(f, "unknown code {c}"),
+        }
+    }
+}
+
+impl From<CloseCode> for VarInt {
+    fn from(code: CloseCode) -> VarInt {
+        VarInt::from_u64(code.code()).unwrap_or(VarInt::MAX)
+    }
+}
+
+impl From<VarInt> for CloseCode {
+    fn from(code: VarInt) -> CloseCode {
+        CloseCode::from_code(code.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: [CloseCode; 9] = [
+        CloseCode::Normal,
+        CloseCode::ProtocolViolation,
+        CloseCode::MessageTooLarge,
+        CloseCode::Unauthorized,
+        CloseCode::ShuttingDown,
+        CloseCode::RateLimited,
+        CloseCode::InternalError,
+        CloseCode::Timeout,
+        CloseCode::TooManyConnections,
+    ];
+
+    #[test]
+    fn round_trips_through_varint() {
+        for code in KNOWN {
+            assert_eq!(CloseCode::from(VarInt::from(code)), code);
+            assert!(!matches!(
+                CloseCode::from_code(code.code()),
+                CloseCode::Unknown(_)
+            ));
+        }
+        for c in [0x09, 0x1234, VarInt::MAX.into_inner()] {
+            let code = CloseCode::from_code(c);
+            assert_eq!(code, CloseCode::Unknown(c));
+            assert_eq!(CloseCode::from(VarInt::from(code)), code);
+        }
+    }
+
+    #[test]
+    fn codes_are_distinct() {
+        for (i, a) in KNOWN.iter().enumerate() {
+            for b in &KNOWN[i + 1..] {
+                assert_ne!(a.code(), b.code());
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_unknown_saturates() {
+        let code = CloseCode::Unknown(u64::MAX);
+        assert_eq!(VarInt::from(code), VarInt::MAX);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::channel::Channel;
+use crate::client::{Client, ClientConfig, Origin};
+use crate::close_code::CloseCode;
+use crate::error::Error;
+use crate::session::EarlyData;
+use mosaic_core::Message;
+use std::net::SocketAddr;
+
+/// A local UDP socket shared by many `Client`s
+///
+/// `ClientConfig::client` opens a new socket for every `Client`. A
+/// `ClientEndpoint` instead opens one socket and connects every `Client` from
+/// it. Each connection still uses its own `ClientConfig`, and so still only
+/// accepts the server key that `ClientConfig` pins.
+///
+/// Cloning a `ClientEndpoint` shares the socket.
+#[derive(Debug, Clone)]
+pub struct ClientEndpoint {
+    endpoint: quinn::Endpoint,
+}
+
+impl ClientEndpoint {
+    /// Create a `ClientEndpoint` bound to `local_socket`
+    ///
+    /// Bind to `(std::net::Ipv6Addr::UNSPECIFIED, 0).into()` to reach both
+    /// IPv6 and IPv4 servers on systems with dual-stack sockets, or to
+    /// `(std::net::Ipv4Addr::UNSPECIFIED, 0).into()` for IPv4 only.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the socket could not be bound
+    pub fn new(local_socket: SocketAddr) -> Result<ClientEndpoint, Error> {
+        Ok(ClientEndpoint {
+            endpoint: quinn::Endpoint::client(local_socket)?,
+        })
+    }
+
+    /// Get at the inner `quinn::Endpoint`
+    #[must_use]
+    pub fn inner(&self) -> &quinn::Endpoint {
+        &self.endpoint
+    }
+
+    /// The local socket address
+    ///
+    /// # Errors
+    ///
+    /// Errors if the socket address could not be determined
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// The number of connections currently open from this endpoint
+    #[must_use]
+    pub fn open_connections(&self) -> usize {
+        self.endpoint.open_connections()
+    }
+
+    /// Create a `Client` by connecting to the `Server` described by `config`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the server could not be connected to.
+    pub async fn connect(&self, config: &ClientConfig) -> Result<Client, Error> {
+        config
+            .client_from(&Origin::Fixed(self.endpoint.clone(), false))
+            .await
+    }
+
+    /// Create a `Client` like `ClientEndpoint::connect`, sending `messages`
+    /// as 0-RTT early data if possible. See
+    /// `ClientConfig::client_with_early_data`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if any message is a Submission, the server could not be
+    /// connected to, or the messages could not be sent.
+    pub async fn connect_with_early_data(
+        &self,
+        config: &ClientConfig,
+        messages: &[Message],
+    ) -> Result<(Client, Channel, EarlyData), Error> {
+        config
+            .client_with_early_data_from(&Origin::Fixed(self.endpoint.clone(), false), messages)
+            .await
+    }
+
+    /// Close every connection from this endpoint and wait for them to finish
+    /// closing.
+    ///
+    /// `reason` will be truncated if it does not fit in a single packet
+    pub async fn close(&self, code: CloseCode, reason: &[u8]) {
+        self.endpoint.close(code.into(), reason);
+        self.endpoint.wait_idle().await;
+    }
+}
@@ -1,3 +1,5 @@
+use crate::close_code::CloseCode;
+use crate::limits::ConnectionLimit;
 use std::convert::Infallible;
 use std::error::Error as StdError;
 use std::panic::Location;
@@ -10,6 +12,15 @@ pub struct Error {
     location: &'static Location<'static>,
 }
 
+impl Error {
+    /// The `CloseCode` the peer gave, if this error came from the peer closing
+    /// the connection, resetting a `Channel`, or stopping a `Channel`
+    #[must_use]
+    pub fn close_code(&self) -> Option<CloseCode> {
+        self.inner.close_code()
+    }
+}
+
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         Some(&self.inner)
@@ -31,21 +42,57 @@ pub enum InnerError {
     /// Channel already finished
     ChannelAlreadyFinished,
 
+    /// Channel was idle for longer than its idle timeout
+    ChannelIdleTimeout,
+
     /// Connect
     ConnectError(quinn::ConnectError),
 
+    /// No candidate address could be connected to before the connect timeout
+    ConnectTimeout,
+
     /// Connection
     ConnectionError(quinn::ConnectionError),
 
+    /// A connection limit was reached
+    ConnectionLimitReached(ConnectionLimit),
+
     /// Endpoint is closed
     EndpointIsClosed,
 
     /// General error
     General(String),
 
+    /// A host name did not resolve
+    HostNotFound(String),
+
+    /// Invalid CIDR address prefix
+    InvalidCidr(String),
+
+    /// Invalid line (at the given line number) in CIDR rules
+    InvalidCidrRule(usize, String),
+
+    /// Invalid host name or IP address
+    InvalidHost(String),
+
+    /// Invalid `host:port`
+    InvalidHostPort(String),
+
+    /// Invalid line (at the given line number) in a known servers file
+    InvalidKnownServersLine(usize, String),
+
+    /// Invalid `ServerLocator`
+    InvalidServerLocator(String),
+
     /// I/O error
     Io(std::io::Error),
 
+    /// Incoming message (of the first size) exceeds the maximum allowed size (the second)
+    MessageTooLarge(usize, usize),
+
+    /// Incoming message was not completed within the message timeout
+    MessageTimeout,
+
     /// Missing ALPN
     MissingAlpn,
 
@@ -55,6 +102,18 @@ pub enum InnerError {
     /// `NoInitialCipherSuite`
     NoInitialCipherSuite(quinn::crypto::rustls::NoInitialCipherSuite),
 
+    /// No server address to connect to
+    NoServerAddress,
+
+    /// A message that is not idempotent was offered as early data
+    NotIdempotent,
+
+    /// Only the given number of bytes were written before a QUIC write error
+    PartialWrite(usize, Box<quinn::WriteError>),
+
+    /// Peer not authorized, with the code the connection was closed with
+    PeerNotAuthorized(CloseCode),
+
     /// Quic Read error
     QuicRead(Box<quinn::ReadError>),
 
@@ -67,15 +126,28 @@ pub enum InnerError {
     /// Retry Error
     RetryError(Box<quinn::RetryError>),
 
+    /// The server (named first) presented a key (the third) other than the
+    /// one known for it (the second)
+    ServerKeyMismatch(String, mosaic_core::PublicKey, mosaic_core::PublicKey),
+
     /// Shutting Down
     ShuttingDown,
 
     /// Stateless Retry was required
+    #[deprecated(
+        note = "`Server::accept` now handles stateless retry itself; this is never returned"
+    )]
     StatelessRetryRequired,
 
     /// TLS
     Tls(rustls::Error),
 
+    /// Token key material (of this many bytes) is too short
+    TokenKeyTooShort(usize),
+
+    /// The transport is not supported
+    UnsupportedTransport(String),
+
     /// Wrong ALPN
     WrongAlpn,
 }
@@ -85,21 +157,53 @@ impl std::fmt::Display for InnerError {
         match self {
             InnerError::AltTls(e) => write!(f, "Alt TLS Error: {e}"),
             InnerError::ChannelAlreadyFinished => write!(f, "Channel already finished"),
+            InnerError::ChannelIdleTimeout => write!(f, "Channel idle timeout"),
             InnerError::ConnectError(e) => write!(f, "QUIC connect error: {e}"),
-            InnerError::ConnectionError(e) => write!(f, "QUIC connection error: {e}"),
+            InnerError::ConnectTimeout => write!(f, "Connect timed out"),
+            InnerError::ConnectionError(e) => match peer_close_code(e) {
+                Some(code) => write!(f, "QUIC connection error ({code}): {e}"),
+                None => write!(f, "QUIC connection error: {e}"),
+            },
+            InnerError::ConnectionLimitReached(l) => write!(f, "Connection limit reached ({l})"),
             InnerError::EndpointIsClosed => write!(f, "Endpoint is closed"),
             InnerError::General(s) => write!(f, "General Error: {s}"),
+            InnerError::HostNotFound(s) => write!(f, "Host not found: {s}"),
+            InnerError::InvalidCidr(s) => write!(f, "Invalid CIDR address prefix: {s}"),
+            InnerError::InvalidCidrRule(n, s) => write!(f, "Invalid CIDR rule on line {n}: {s}"),
+            InnerError::InvalidHost(s) => write!(f, "Invalid host: {s}"),
+            InnerError::InvalidHostPort(s) => write!(f, "Invalid host:port: {s}"),
+            InnerError::InvalidKnownServersLine(n, s) => {
+                write!(f, "Invalid known servers line {n}: {s}")
+            }
+            InnerError::InvalidServerLocator(s) => write!(f, "Invalid server locator: {s}"),
             InnerError::Io(e) => write!(f, "I/O Error: {e}"),
+            InnerError::MessageTooLarge(size, max) => {
+                write!(f, "Message too large: {size} bytes (max {max})")
+            }
+            InnerError::MessageTimeout => write!(f, "Message not completed in time"),
             InnerError::MissingAlpn => write!(f, "ALPN not specified by peer"),
             InnerError::MosaicCore(e) => write!(f, "Mosaic error: {e}"),
             InnerError::NoInitialCipherSuite(_) => write!(f, "No initial cipher suite"),
+            InnerError::NoServerAddress => write!(f, "No server address to connect to"),
+            InnerError::NotIdempotent => write!(f, "Message is not idempotent, cannot send early"),
+            InnerError::PartialWrite(n, e) => {
+                write!(f, "Partial write ({n} bytes written): {e}")
+            }
+            InnerError::PeerNotAuthorized(code) => write!(f, "Peer not authorized ({code})"),
             InnerError::QuicRead(e) => write!(f, "QUIC read error: {e}"),
             InnerError::QuicWrite(e) => write!(f, "QUIC write error: {e}"),
             InnerError::RemoteAddressNotApproved => write!(f, "Remote address not approved"),
             InnerError::RetryError(e) => write!(f, "QUIC retry error: {e}"),
+            InnerError::ServerKeyMismatch(s, known, presented) => write!(
+                f,
+                "Server key mismatch for {s}: expected {known}, presented {presented}"
+            ),
             InnerError::ShuttingDown => write!(f, "Shutting down"),
+            #[allow(deprecated)]
             InnerError::StatelessRetryRequired => write!(f, "Stateless retry required"),
             InnerError::Tls(e) => write!(f, "TLS Error: {e}"),
+            InnerError::TokenKeyTooShort(n) => write!(f, "Token key too short ({n} bytes)"),
+            InnerError::UnsupportedTransport(s) => write!(f, "Unsupported transport: {s}"),
             InnerError::WrongAlpn => write!(f, "Wrong ALPN (peer did not specify mosaic)"),
         }
     }
@@ -114,8 +218,8 @@ impl StdError for InnerError {
             InnerError::Io(e) => Some(e),
             InnerError::MosaicCore(e) => Some(e),
             InnerError::NoInitialCipherSuite(e) => Some(e),
+            InnerError::PartialWrite(_, e) | InnerError::QuicWrite(e) => Some(e),
             InnerError::QuicRead(e) => Some(e),
-            InnerError::QuicWrite(e) => Some(e),
             InnerError::RetryError(e) => Some(e),
             InnerError::Tls(e) => Some(e),
             _ => None,
@@ -123,6 +227,35 @@ impl StdError for InnerError {
     }
 }
 
+impl InnerError {
+    /// The `CloseCode` the peer gave, if this error came from the peer closing
+    /// the connection, resetting a `Channel`, or stopping a `Channel`
+    #[must_use]
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            InnerError::ConnectionError(e) => peer_close_code(e),
+            InnerError::QuicRead(e) => match &**e {
+                quinn::ReadError::Reset(code) => Some((*code).into()),
+                quinn::ReadError::ConnectionLost(e) => peer_close_code(e),
+                _ => None,
+            },
+            InnerError::PartialWrite(_, e) | InnerError::QuicWrite(e) => match &**e {
+                quinn::WriteError::Stopped(code) => Some((*code).into()),
+                quinn::WriteError::ConnectionLost(e) => peer_close_code(e),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn peer_close_code(e: &quinn::ConnectionError) -> Option<CloseCode> {
+    match e {
+        quinn::ConnectionError::ApplicationClosed(close) => Some(close.error_code.into()),
+        _ => None,
+    }
+}
+
 // Note: we impl Into because our typical pattern is InnerError::Variant.into()
 //       when we tried implementing From, the location was deep in rust code's
 //       blanket into implementation, which wasn't the line number we wanted.
@@ -275,3 +408,49 @@ impl From<rustls::Error> for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quinn::VarInt;
+
+    fn closed(code: CloseCode) -> quinn::ConnectionError {
+        quinn::ConnectionError::ApplicationClosed(quinn::ApplicationClose {
+            error_code: code.into(),
+            reason: b"bye".to_vec().into(),
+        })
+    }
+
+    #[test]
+    fn close_code_from_application_closed() {
+        let e: Error = closed(CloseCode::ShuttingDown).into();
+        assert_eq!(e.close_code(), Some(CloseCode::ShuttingDown));
+
+        let e: Error = quinn::ConnectionError::TimedOut.into();
+        assert_eq!(e.close_code(), None);
+    }
+
+    #[test]
+    fn close_code_from_reset() {
+        let e: Error = quinn::ReadError::Reset(CloseCode::RateLimited.into()).into();
+        assert_eq!(e.close_code(), Some(CloseCode::RateLimited));
+
+        let e: Error = quinn::ReadError::ConnectionLost(closed(CloseCode::Unauthorized)).into();
+        assert_eq!(e.close_code(), Some(CloseCode::Unauthorized));
+
+        let e: Error = quinn::ReadError::ClosedStream.into();
+        assert_eq!(e.close_code(), None);
+    }
+
+    #[test]
+    fn close_code_from_stopped() {
+        let e: Error = quinn::WriteError::Stopped(CloseCode::MessageTooLarge.into()).into();
+        assert_eq!(e.close_code(), Some(CloseCode::MessageTooLarge));
+
+        let partial = InnerError::PartialWrite(
+            10,
+            Box::new(quinn::WriteError::Stopped(VarInt::from_u32(0x1234))),
+        );
+        assert_eq!(partial.close_code(), Some(CloseCode::Unknown(0x1234)));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::error::Error;
+use alt_tls::SelfSignedCertificateVerifier;
+use mosaic_core::{PublicKey, SecretKey};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+// A TLS server name unique to the server's public key
+pub(crate) fn server_name(public_key: PublicKey) -> String {
+    use std::fmt::Write;
+
+    // Hex in two halves, as DNS labels may be at most 63 characters
+    let mut name = String::with_capacity(72);
+    for (i, b) in public_key.as_bytes().iter().enumerate() {
+        if i == 16 {
+            name.push('.');
+        }
+        let _ = write!(name, "{b:02x}");
+    }
+    name.push_str(".mosaic");
+    name
+}
+
+// The Mosaic public key in a certificate
+fn certificate_public_key(cert: &CertificateDer<'_>) -> Option<PublicKey> {
+    alt_tls::public_key_from_certificate_der(cert)
+        .ok()
+        .map(|vk| PublicKey::from_verifying_key(&vk))
+}
+
+// The Mosaic public key the peer of `connection` presented, if any
+pub(crate) fn peer_public_key(connection: &quinn::Connection) -> Option<PublicKey> {
+    let id = connection.peer_identity()?;
+    match id.downcast_ref::<Vec<CertificateDer>>() {
+        Some(vec) => vec.iter().find_map(certificate_public_key),
+        None => panic!("Invalid downcast code"),
+    }
+}
+
+// A self-signed Mosaic TLS identity for `secret_key`
+pub(crate) fn certified_key(
+    secret_key: &SecretKey,
+    provider: &CryptoProvider,
+) -> Result<Arc<CertifiedKey>, Error> {
+    let (certificate_der, private_key_der) = alt_tls::self_signed_tls_identity(
+        &secret_key.to_signing_key(),
+        vec![
+            "mosaic".to_string(),
+            "IGNORE THE NAME, DETERMINE TRUST FROM THE KEY".to_string(),
+        ],
+    )?;
+    let signing_key = provider.key_provider.load_private_key(private_key_der)?;
+    Ok(Arc::new(CertifiedKey::new(
+        vec![certificate_der],
+        signing_key,
+    )))
+}
+
+// Verifies that the server presents a valid self-signed certificate for one
+// of the accepted keys, or for any key
+#[derive(Debug)]
+pub(crate) struct AcceptedKeysVerifier {
+    inner: SelfSignedCertificateVerifier,
+    accepted: Option<Vec<PublicKey>>,
+
+    // A key that was presented but not accepted, to report why
+    refused: Mutex<Option<PublicKey>>,
+}
+
+impl AcceptedKeysVerifier {
+    pub(crate) fn new(accepted: Vec<PublicKey>) -> AcceptedKeysVerifier {
+        AcceptedKeysVerifier {
+            inner: SelfSignedCertificateVerifier::new(
+                alt_tls::SUPPORTED_ALGORITHMS,
+                vec![SignatureScheme::ED25519],
+                None, // we check the key ourselves
+            ),
+            accepted: Some(accepted),
+            refused: Mutex::new(None),
+        }
+    }
+
+    // Accept any key (for trust on first use, which checks afterwards)
+    pub(crate) fn any() -> AcceptedKeysVerifier {
+        AcceptedKeysVerifier {
+            accepted: None,
+            ..AcceptedKeysVerifier::new(Vec::new())
+        }
+    }
+
+    // The first accepted key, and the key presented instead, if a key was
+    // refused
+    pub(crate) fn refused(&self) -> Option<(PublicKey, PublicKey)> {
+        let refused = *self.refused.lock().unwrap_or_else(PoisonError::into_inner);
+        let expected = self.accepted.as_ref()?.first()?;
+        Some((*expected, refused?))
+    }
+}
+
+impl ServerCertVerifier for AcceptedKeysVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        match certificate_public_key(end_entity) {
+            Some(key) if self.accepted.as_ref().is_none_or(|a| a.contains(&key)) => Ok(verified),
+            Some(key) => {
+                *self.refused.lock().unwrap_or_else(PoisonError::into_inner) = Some(key);
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+            None => Err(rustls::Error::InvalidCertificate(
+                CertificateError::BadEncoding,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+// Presents the current key, except to clients asking (by server name) for a
+// previous key that is still within its transition window
+#[derive(Debug)]
+pub(crate) struct RotatingKeyResolver {
+    current: Arc<CertifiedKey>,
+    previous: Vec<(String, Arc<CertifiedKey>, SystemTime)>,
+}
+
+impl RotatingKeyResolver {
+    pub(crate) fn new(
+        current: &SecretKey,
+        previous: &[(SecretKey, SystemTime)],
+        provider: &CryptoProvider,
+    ) -> Result<RotatingKeyResolver, Error> {
+        Ok(RotatingKeyResolver {
+            current: certified_key(current, provider)?,
+            previous: previous
+                .iter()
+                .map(|(sk, until)| {
+                    Ok((
+                        server_name(sk.public()),
+                        certified_key(sk, provider)?,
+                        *until,
+                    ))
+                })
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+}
+
+impl ResolvesServerCert for RotatingKeyResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        if let Some(name) = client_hello.server_name() {
+            let now = SystemTime::now();
+            for (previous_name, key, until) in &self.previous {
+                if name.eq_ignore_ascii_case(previous_name) && now < *until {
+                    return Some(key.clone());
+                }
+            }
+        }
+        Some(self.current.clone())
+    }
+}
@@ -0,0 +1,318 @@
+use crate::error::{Error, InnerError};
+use mosaic_core::PublicKey;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+/// A store of the keys servers presented when first connected to, for
+/// trust-on-first-use. See `ClientConfig::trust_on_first_use`.
+///
+/// Servers are named by the `host:port` they were reached at, in lowercase.
+///
+/// `ClientConfig` calls `learn` on a blocking thread, so it may do blocking
+/// I/O. `known_key` is called on the async runtime, so it should not block.
+pub trait KnownServers: std::fmt::Debug + Send + Sync {
+    /// The key known for `server`, if any
+    ///
+    /// # Errors
+    ///
+    /// Errors if the store could not be read
+    fn known_key(&self, server: &str) -> Result<Option<PublicKey>, Error>;
+
+    /// Remember that `server` has `key`
+    ///
+    /// # Errors
+    ///
+    /// Errors if a different key is already known for `server`, or if the
+    /// store could not be written
+    fn learn(&self, server: &str, key: PublicKey) -> Result<(), Error>;
+
+    /// Forget the key of `server`, such as after it has legitimately changed
+    ///
+    /// # Errors
+    ///
+    /// Errors if the store could not be written
+    fn forget(&self, server: &str) -> Result<(), Error>;
+}
+
+// Insert `key` for `server` (already lowercase) unless a different key is
+// known
+fn learn_into(
+    map: &mut BTreeMap<String, PublicKey>,
+    server: String,
+    key: PublicKey,
+) -> Result<bool, Error> {
+    match map.get(&server) {
+        Some(known) if *known == key => Ok(false),
+        Some(known) => Err(InnerError::ServerKeyMismatch(server, *known, key).into()),
+        None => {
+            let _ = map.insert(server, key);
+            Ok(true)
+        }
+    }
+}
+
+/// A `KnownServers` store kept in memory
+#[derive(Debug, Default)]
+pub struct MemoryKnownServers {
+    keys: RwLock<BTreeMap<String, PublicKey>>,
+}
+
+impl MemoryKnownServers {
+    /// Create an empty `MemoryKnownServers`
+    #[must_use]
+    pub fn new() -> MemoryKnownServers {
+        MemoryKnownServers::default()
+    }
+}
+
+impl KnownServers for MemoryKnownServers {
+    fn known_key(&self, server: &str) -> Result<Option<PublicKey>, Error> {
+        Ok(self
+            .keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&server.to_ascii_lowercase())
+            .copied())
+    }
+
+    fn learn(&self, server: &str, key: PublicKey) -> Result<(), Error> {
+        let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+        let _ = learn_into(&mut keys, server.to_ascii_lowercase(), key)?;
+        Ok(())
+    }
+
+    fn forget(&self, server: &str) -> Result<(), Error> {
+        let _ = self
+            .keys
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&server.to_ascii_lowercase());
+        Ok(())
+    }
+}
+
+/// A `KnownServers` store kept in a text file, one server per line:
+///
+/// ```text
+/// # Comments and blank lines are ignored
+/// relay.example.com:8081 mopub0...
+/// [2001:db8::1]:8081 mopub0...
+/// ```
+///
+/// Server names are case insensitive, and are written in lowercase. The file
+/// is rewritten whenever a key is learned or forgotten, without holding up
+/// lookups.
+#[derive(Debug)]
+pub struct FileKnownServers {
+    path: PathBuf,
+    keys: RwLock<BTreeMap<String, PublicKey>>,
+    // Held while changing and rewriting, so that writes land in order
+    saving: Mutex<()>,
+}
+
+impl FileKnownServers {
+    /// Open the store at `path`. The file is created when the first key is
+    /// learned if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file exists but cannot be read or contains an invalid line
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileKnownServers, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut keys = BTreeMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                for (i, line) in contents.lines().enumerate() {
+                    let line = line.split('#').next().unwrap_or("").trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let invalid =
+                        || InnerError::InvalidKnownServersLine(i + 1, line.to_owned()).into_err();
+                    let mut words = line.split_whitespace();
+                    let (Some(server), Some(key), None) =
+                        (words.next(), words.next(), words.next())
+                    else {
+                        return Err(invalid());
+                    };
+                    let key = PublicKey::from_printable(key).map_err(|_| invalid())?;
+                    let _ = keys.insert(server.to_ascii_lowercase(), key);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(FileKnownServers {
+            path,
+            keys: RwLock::new(keys),
+            saving: Mutex::new(()),
+        })
+    }
+
+    /// The path of the file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Write out `contents` as the whole file, replacing it atomically. The
+    // temporary file is unique, so other processes (or other stores on the
+    // same path) saving at the same time cannot clobber it.
+    fn save(&self, contents: &str) -> Result<(), Error> {
+        static SAVES: AtomicU64 = AtomicU64::new(0);
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            SAVES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result =
+            std::fs::write(&tmp, contents).and_then(|()| std::fs::rename(&tmp, &self.path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        Ok(result?)
+    }
+}
+
+// The contents of a known servers file holding `keys`
+fn contents(keys: &BTreeMap<String, PublicKey>) -> String {
+    let mut contents = String::new();
+    for (server, key) in keys {
+        contents.push_str(server);
+        contents.push(' ');
+        contents.push_str(&key.as_printable());
+        contents.push('\n');
+    }
+    contents
+}
+
+impl KnownServers for FileKnownServers {
+    fn known_key(&self, server: &str) -> Result<Option<PublicKey>, Error> {
+        Ok(self
+            .keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&server.to_ascii_lowercase())
+            .copied())
+    }
+
+    // The keys are only locked to change them, not while the file is written
+    fn learn(&self, server: &str, key: PublicKey) -> Result<(), Error> {
+        let _saving = self.saving.lock().unwrap_or_else(PoisonError::into_inner);
+        let server = server.to_ascii_lowercase();
+        let contents = {
+            let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+            if !learn_into(&mut keys, server.clone(), key)? {
+                return Ok(());
+            }
+            contents(&keys)
+        };
+        if let Err(e) = self.save(&contents) {
+            let _ = self
+                .keys
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&server);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn forget(&self, server: &str) -> Result<(), Error> {
+        let _saving = self.saving.lock().unwrap_or_else(PoisonError::into_inner);
+        let contents = {
+            let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+            if keys.remove(&server.to_ascii_lowercase()).is_none() {
+                return Ok(());
+            }
+            contents(&keys)
+        };
+        self.save(&contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::SecretKey;
+
+    // A path in the temp directory unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "mosaic-net-known-servers-{}-{name}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn file_names_are_case_insensitive() {
+        let path = temp_path("case");
+        let first = SecretKey::generate().public();
+        let second = SecretKey::generate().public();
+        std::fs::write(
+            &path,
+            format!("Relay.Example.COM:8081 {}\n", first.as_printable()),
+        )
+        .unwrap();
+
+        let store = FileKnownServers::open(&path).unwrap();
+        assert_eq!(
+            store.known_key("relay.example.com:8081").unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            store.known_key("RELAY.example.com:8081").unwrap(),
+            Some(first)
+        );
+
+        store.learn("Other.Example.com:1", second).unwrap();
+        let e = store.learn("other.example.COM:1", first).unwrap_err();
+        assert!(matches!(e.inner, InnerError::ServerKeyMismatch(..)));
+
+        // Everything is written back in lowercase, and reads back the same
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            format!(
+                "other.example.com:1 {}\nrelay.example.com:8081 {}\n",
+                second.as_printable(),
+                first.as_printable()
+            )
+        );
+        let reopened = FileKnownServers::open(&path).unwrap();
+        assert_eq!(
+            reopened.known_key("OTHER.example.com:1").unwrap(),
+            Some(second)
+        );
+
+        reopened.forget("RELAY.EXAMPLE.COM:8081").unwrap();
+        assert_eq!(reopened.known_key("relay.example.com:8081").unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn memory_names_are_case_insensitive() {
+        let store = MemoryKnownServers::new();
+        let key = SecretKey::generate().public();
+        store.learn("Relay.Example.com:8081", key).unwrap();
+        assert_eq!(
+            store.known_key("relay.example.COM:8081").unwrap(),
+            Some(key)
+        );
+        store.forget("RELAY.example.com:8081").unwrap();
+        assert_eq!(store.known_key("relay.example.com:8081").unwrap(), None);
+    }
+
+    #[test]
+    fn failed_save_leaves_no_key_or_temp_file() {
+        let dir = temp_path("missing-dir");
+        let store = FileKnownServers::open(dir.join("known_servers")).unwrap();
+        let key = SecretKey::generate().public();
+        assert!(store.learn("relay.example.com:8081", key).is_err());
+        assert_eq!(store.known_key("relay.example.com:8081").unwrap(), None);
+        assert!(!dir.exists());
+    }
+}
@@ -28,14 +28,60 @@ pub use error::{Error, InnerError};
 /// The Application-Layer protocol string used within QUIC for Mosaic
 pub const ALPN_QUIC_MOSAIC: &[u8] = b"mosaic";
 
+mod identity;
+
 mod client;
-pub use client::{Client, ClientConfig};
+pub use client::{Client, ClientConfig, DEFAULT_CONNECT_STAGGER, DEFAULT_CONNECT_TIMEOUT};
+
+mod locator;
+pub use locator::{SERVER_LOCATOR_SCHEME, ServerLocator};
+
+mod known_servers;
+pub use known_servers::{FileKnownServers, KnownServers, MemoryKnownServers};
+
+mod resolver;
+pub use resolver::{ResolveFuture, Resolver, StaticResolver, SystemResolver};
+
+mod endpoint;
+pub use endpoint::ClientEndpoint;
+
+mod reconnect;
+pub use reconnect::{
+    Backoff, ConnectionEvents, ConnectionState, GiveUpReason, OnConnect, ReconnectingClient,
+};
 
 mod server;
 pub use server::{
-    AlwaysAllowedApprover, Approval, Approver, ClientConnection, IncomingClient, Server,
-    ServerConfig,
+    AlwaysAllowedApprover, AlwaysAllowedAuthorizer, Approval, Approver, AsyncApprover,
+    AsyncPeerAuthorizer, Authorization, ClientConnection, DEFAULT_MAX_CHANNELS, IncomingClient,
+    MIN_TOKEN_KEY_LEN, PeerAuthorizer, RetryPolicy, Server, ServerConfig, TokenKey,
 };
 
+mod approvers;
+pub use approvers::{And, ApproverChain, ApproverExt, FirstRefusal, FnApprover, Or};
+
+mod cidr;
+pub use cidr::{Cidr, CidrApprover, CidrRules};
+
+mod limits;
+pub use limits::{ConnectionLimit, ConnectionLimits};
+
+mod rate_limit;
+pub use rate_limit::RateLimitApprover;
+
 mod channel;
-pub use channel::Channel;
+pub use channel::{
+    Channel, ChannelOptions, ChannelReceiver, ChannelSender, DEFAULT_MAX_MESSAGE_SIZE,
+};
+
+mod transport;
+pub use transport::{CongestionController, TransportOptions};
+
+mod close_code;
+pub use close_code::CloseCode;
+
+mod session;
+pub use session::{EarlyData, SessionStore};
+
+mod stats;
+pub use stats::ConnectionStats;
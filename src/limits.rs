@@ -0,0 +1,281 @@
+use mosaic_core::PublicKey;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Caps on the number of simultaneous `ClientConnection`s a `Server` will
+/// hold. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Maximum connections overall
+    pub max_total: Option<usize>,
+
+    /// Maximum connections from any one IP address
+    pub max_per_ip: Option<usize>,
+
+    /// Maximum connections authenticated as any one `PublicKey`
+    pub max_per_peer: Option<usize>,
+}
+
+/// Which of the `ConnectionLimits` was reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionLimit {
+    /// `max_total`
+    Total,
+
+    /// `max_per_ip`
+    PerIp,
+
+    /// `max_per_peer`
+    PerPeer,
+}
+
+impl std::fmt::Display for ConnectionLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionLimit::Total => write!(f, "total"),
+            ConnectionLimit::PerIp => write!(f, "per IP"),
+            ConnectionLimit::PerPeer => write!(f, "per peer"),
+        }
+    }
+}
+
+/// Counts live connections against the `ConnectionLimits`
+#[derive(Debug)]
+pub(crate) struct ConnectionTracker {
+    limits: ConnectionLimits,
+    counts: Mutex<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+    per_peer: HashMap<PublicKey, usize>,
+}
+
+impl ConnectionTracker {
+    pub(crate) fn new(limits: ConnectionLimits) -> ConnectionTracker {
+        ConnectionTracker {
+            limits,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    /// The number of live connections
+    pub(crate) fn total(&self) -> usize {
+        self.counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .total
+    }
+
+    /// Reserve a slot for a connection from `ip`, if the limits allow it
+    pub(crate) fn reserve(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionSlot, ConnectionLimit> {
+        let ip = ip.to_canonical();
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.limits.max_total.is_some_and(|max| counts.total >= max) {
+            return Err(ConnectionLimit::Total);
+        }
+        let per_ip = counts.per_ip.get(&ip).copied().unwrap_or(0);
+        if self.limits.max_per_ip.is_some_and(|max| per_ip >= max) {
+            return Err(ConnectionLimit::PerIp);
+        }
+        counts.total += 1;
+        *counts.per_ip.entry(ip).or_insert(0) += 1;
+        Ok(ConnectionSlot {
+            tracker: self.clone(),
+            ip,
+            peer: None,
+        })
+    }
+}
+
+/// A reserved place within the `ConnectionLimits`, released on drop
+#[derive(Debug)]
+pub(crate) struct ConnectionSlot {
+    tracker: Arc<ConnectionTracker>,
+    ip: IpAddr,
+    peer: Option<PublicKey>,
+}
+
+impl ConnectionSlot {
+    /// Count this connection against `peer` as well, if the limits allow it
+    pub(crate) fn set_peer(&mut self, peer: PublicKey) -> Result<(), ConnectionLimit> {
+        let mut counts = self
+            .tracker
+            .counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let per_peer = counts.per_peer.get(&peer).copied().unwrap_or(0);
+        if self
+            .tracker
+            .limits
+            .max_per_peer
+            .is_some_and(|max| per_peer >= max)
+        {
+            return Err(ConnectionLimit::PerPeer);
+        }
+        *counts.per_peer.entry(peer).or_insert(0) += 1;
+        self.peer = Some(peer);
+        Ok(())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self
+            .tracker
+            .counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        counts.total = counts.total.saturating_sub(1);
+        decrement(&mut counts.per_ip, self.ip);
+        if let Some(peer) = self.peer {
+            decrement(&mut counts.per_peer, peer);
+        }
+    }
+}
+
+fn decrement<K: Hash + Eq>(map: &mut HashMap<K, usize>, key: K) {
+    if let Entry::Occupied(mut entry) = map.entry(key) {
+        *entry.get_mut() -= 1;
+        if *entry.get() == 0 {
+            let _ = entry.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::SecretKey;
+
+    fn tracker(limits: ConnectionLimits) -> Arc<ConnectionTracker> {
+        Arc::new(ConnectionTracker::new(limits))
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn peer_count(tracker: &ConnectionTracker, peer: PublicKey) -> usize {
+        let counts = tracker.counts.lock().unwrap();
+        counts.per_peer.get(&peer).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let tracker = tracker(ConnectionLimits::default());
+        let slots: Vec<_> = (0..100)
+            .map(|_| tracker.reserve(ip("192.0.2.1")).unwrap())
+            .collect();
+        assert_eq!(tracker.total(), 100);
+        drop(slots);
+        assert_eq!(tracker.total(), 0);
+    }
+
+    #[test]
+    fn total_limit() {
+        let tracker = tracker(ConnectionLimits {
+            max_total: Some(2),
+            ..ConnectionLimits::default()
+        });
+        let a = tracker.reserve(ip("192.0.2.1")).unwrap();
+        let _b = tracker.reserve(ip("192.0.2.2")).unwrap();
+        assert_eq!(
+            tracker.reserve(ip("192.0.2.3")).unwrap_err(),
+            ConnectionLimit::Total
+        );
+
+        // A refused reservation takes nothing, and dropping frees a place
+        assert_eq!(tracker.total(), 2);
+        drop(a);
+        assert_eq!(tracker.total(), 1);
+        let _c = tracker.reserve(ip("192.0.2.3")).unwrap();
+    }
+
+    #[test]
+    fn per_ip_limit() {
+        let tracker = tracker(ConnectionLimits {
+            max_per_ip: Some(2),
+            ..ConnectionLimits::default()
+        });
+        let a = tracker.reserve(ip("192.0.2.1")).unwrap();
+        let _b = tracker.reserve(ip("192.0.2.1")).unwrap();
+        assert_eq!(
+            tracker.reserve(ip("192.0.2.1")).unwrap_err(),
+            ConnectionLimit::PerIp
+        );
+
+        // IPv4-mapped addresses count as their IPv4 address
+        assert_eq!(
+            tracker.reserve(ip("::ffff:192.0.2.1")).unwrap_err(),
+            ConnectionLimit::PerIp
+        );
+        let _other = tracker.reserve(ip("192.0.2.2")).unwrap();
+
+        drop(a);
+        let _c = tracker.reserve(ip("::ffff:192.0.2.1")).unwrap();
+        assert_eq!(tracker.total(), 3);
+    }
+
+    #[test]
+    fn per_peer_limit() {
+        let tracker = tracker(ConnectionLimits {
+            max_per_peer: Some(1),
+            ..ConnectionLimits::default()
+        });
+        let peer = SecretKey::generate().public();
+
+        let mut a = tracker.reserve(ip("192.0.2.1")).unwrap();
+        a.set_peer(peer).unwrap();
+        assert_eq!(peer_count(&tracker, peer), 1);
+
+        // From another address makes no difference
+        let mut b = tracker.reserve(ip("192.0.2.2")).unwrap();
+        assert_eq!(b.set_peer(peer).unwrap_err(), ConnectionLimit::PerPeer);
+        assert_eq!(peer_count(&tracker, peer), 1);
+
+        // Other peers and anonymous connections are not affected
+        let mut c = tracker.reserve(ip("192.0.2.2")).unwrap();
+        c.set_peer(SecretKey::generate().public()).unwrap();
+
+        // The refused slot still holds its place until dropped, without
+        // releasing the peer it was refused for
+        assert_eq!(tracker.total(), 3);
+        drop(b);
+        assert_eq!(tracker.total(), 2);
+        assert_eq!(peer_count(&tracker, peer), 1);
+
+        drop(a);
+        assert_eq!(peer_count(&tracker, peer), 0);
+        let mut d = tracker.reserve(ip("192.0.2.3")).unwrap();
+        d.set_peer(peer).unwrap();
+    }
+
+    #[test]
+    fn drop_releases_everything() {
+        let tracker = tracker(ConnectionLimits {
+            max_total: Some(10),
+            max_per_ip: Some(10),
+            max_per_peer: Some(10),
+        });
+        let peer = SecretKey::generate().public();
+        let slots: Vec<_> = (0..5)
+            .map(|_| {
+                let mut slot = tracker.reserve(ip("2001:db8::1")).unwrap();
+                slot.set_peer(peer).unwrap();
+                slot
+            })
+            .collect();
+        drop(slots);
+
+        let counts = tracker.counts.lock().unwrap();
+        assert_eq!(counts.total, 0);
+        assert!(counts.per_ip.is_empty());
+        assert!(counts.per_peer.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::error::{Error, InnerError};
+use crate::resolver::split_host_port;
+use mosaic_core::PublicKey;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+/// The URL scheme of a `ServerLocator`
+pub const SERVER_LOCATOR_SCHEME: &str = "mosaic";
+
+/// Where to find a Mosaic server, and the key it must present
+///
+/// The textual form is URL-like, with an optional transport:
+///
+/// ```text
+/// mosaic://mopub0...@relay.example.com:8081
+/// mosaic://mopub0...@[2001:db8::1]:8081?transport=quic
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerLocator {
+    public_key: PublicKey,
+    host: String,
+    port: u16,
+    transport: Option<String>,
+}
+
+impl ServerLocator {
+    /// Create a `ServerLocator`. `host` may be a host name or an IP address.
+    /// An IPv6 address may be in brackets.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `host` is empty, has unbalanced brackets, or contains
+    /// characters that cannot appear in a host name
+    pub fn new(public_key: PublicKey, host: &str, port: u16) -> Result<ServerLocator, Error> {
+        Ok(ServerLocator {
+            public_key,
+            host: check_host(host)?.to_owned(),
+            port,
+            transport: None,
+        })
+    }
+
+    /// Set the transport, such as `quic`
+    #[must_use]
+    pub fn with_transport(mut self, transport: &str) -> ServerLocator {
+        self.transport = Some(transport.to_ascii_lowercase());
+        self
+    }
+
+    /// The public key the server must present
+    #[must_use]
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// The host name or IP address of the server
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The port of the server
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The transport, if specified
+    #[must_use]
+    pub fn transport(&self) -> Option<&str> {
+        self.transport.as_deref()
+    }
+
+    /// The host and port as `host:port`, with IPv6 addresses in brackets
+    #[must_use]
+    pub fn host_port(&self) -> String {
+        match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => format!("[{ip}]:{}", self.port),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+// Check `host` is a host name or IP address, and strip any brackets from an
+// IPv6 address
+fn check_host(host: &str) -> Result<&str, Error> {
+    let invalid = || InnerError::InvalidHost(host.to_owned()).into_err();
+    let bare = match host.strip_prefix('[') {
+        Some(h) => h.strip_suffix(']').ok_or_else(invalid)?,
+        None => host,
+    };
+    if bare.contains(':') || bare.len() != host.len() {
+        // Only IPv6 addresses have colons, and only they may be bracketed
+        let _: Ipv6Addr = bare.parse().map_err(|_| invalid())?;
+    } else if bare.is_empty()
+        || bare
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "/@[]?#".contains(c))
+    {
+        return Err(invalid());
+    }
+    Ok(bare)
+}
+
+impl FromStr for ServerLocator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ServerLocator, Error> {
+        let invalid = || InnerError::InvalidServerLocator(s.to_owned()).into_err();
+
+        let (scheme, rest) = s.split_once("://").ok_or_else(invalid)?;
+        if !scheme.eq_ignore_ascii_case(SERVER_LOCATOR_SCHEME) {
+            return Err(invalid());
+        }
+
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+
+        let (key, host_port) = rest.split_once('@').ok_or_else(invalid)?;
+        let public_key = PublicKey::from_printable(key).map_err(|_| invalid())?;
+        let (host, port) = split_host_port(host_port).map_err(|_| invalid())?;
+        let mut locator = ServerLocator::new(public_key, &host, port).map_err(|_| invalid())?;
+
+        // Unknown parameters are ignored, for forwards compatibility
+        for param in query.into_iter().flat_map(|q| q.split('&')) {
+            if let Some(("transport", transport)) = param.split_once('=') {
+                if transport.is_empty() {
+                    return Err(invalid());
+                }
+                locator = locator.with_transport(transport);
+            }
+        }
+
+        Ok(locator)
+    }
+}
+
+impl std::fmt::Display for ServerLocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{SERVER_LOCATOR_SCHEME}://{}@{}",
+            self.public_key.as_printable(),
+            self.host_port()
+        )?;
+        if let Some(transport) = &self.transport {
+            write!(f, "?transport={transport}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::SecretKey;
+
+    #[test]
+    fn round_trips_through_text() {
+        let key = SecretKey::generate().public();
+        for (host, transport) in [
+            ("relay.example.com", None),
+            ("192.0.2.1", Some("quic")),
+            ("2001:db8::1", None),
+            ("[2001:db8::1]", Some("quic")),
+        ] {
+            let mut locator = ServerLocator::new(key, host, 8081).unwrap();
+            if let Some(transport) = transport {
+                locator = locator.with_transport(transport);
+            }
+            let text = locator.to_string();
+            assert_eq!(text.parse::<ServerLocator>().unwrap(), locator, "{text}");
+        }
+    }
+
+    #[test]
+    fn parses_parts() {
+        let key = SecretKey::generate().public();
+        let text = format!(
+            "MOSAIC://{}@[2001:db8::1]:443/?foo=bar&transport=QUIC",
+            key.as_printable()
+        );
+        let locator: ServerLocator = text.parse().unwrap();
+        assert_eq!(locator.public_key(), key);
+        assert_eq!(locator.host(), "2001:db8::1");
+        assert_eq!(locator.port(), 443);
+        assert_eq!(locator.transport(), Some("quic"));
+        assert_eq!(locator.host_port(), "[2001:db8::1]:443");
+        assert_eq!(
+            locator.to_string(),
+            format!(
+                "mosaic://{}@[2001:db8::1]:443?transport=quic",
+                key.as_printable()
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let key = SecretKey::generate().public().as_printable();
+        for text in [
+            format!("https://{key}@relay.example.com:8081"),
+            format!("mosaic://{key}relay.example.com:8081"),
+            format!("mosaic://{key}@relay.example.com"),
+            format!("mosaic://{key}@host/x:80"),
+            format!("mosaic://{key}@user@host:80"),
+            format!("mosaic://{key}@[relay.example.com]:80"),
+            format!("mosaic://{key}@relay.example.com:8081?transport="),
+            "mosaic://notakey@relay.example.com:8081".to_owned(),
+        ] {
+            assert!(
+                matches!(
+                    text.parse::<ServerLocator>().map_err(|e| e.inner),
+                    Err(InnerError::InvalidServerLocator(_))
+                ),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn new_rejects_invalid_hosts() {
+        let key = SecretKey::generate().public();
+        for host in [
+            "",
+            "[2001:db8::1",
+            "2001:db8::1]",
+            "[]",
+            "a b",
+            "host/x",
+            "a:b",
+        ] {
+            assert!(ServerLocator::new(key, host, 80).is_err(), "{host}");
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::cidr::Cidr;
+use crate::server::{Approval, Approver};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// Don't bother pruning idle buckets until we are tracking at least this many
+const MIN_PRUNE_THRESHOLD: usize = 1024;
+
+/// An `Approver` that limits how often each source address may connect,
+/// using a token bucket per address.
+///
+/// Each source may make `burst` connection attempts at once, and regains
+/// that allowance evenly over `per`. Once a source is out of budget it is
+/// silently refused, so a misbehaving reconnect loop gets no response.
+///
+/// IPv6 sources are grouped by their /64 prefix by default, since a single
+/// host usually controls a whole /64. IPv4 sources are tracked individually.
+#[derive(Debug)]
+pub struct RateLimitApprover {
+    burst: f64,
+    per_second: f64,
+    ipv4_prefix_len: u8,
+    ipv6_prefix_len: u8,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    map: HashMap<IpAddr, Bucket>,
+    prune_threshold: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimitApprover {
+    /// Create a `RateLimitApprover` that allows `burst` connection attempts
+    /// per source, replenished over `per`
+    #[must_use]
+    pub fn new(burst: u32, per: Duration) -> RateLimitApprover {
+        let burst = f64::from(burst.max(1));
+        RateLimitApprover {
+            burst,
+            per_second: burst / per.as_secs_f64().max(f64::EPSILON),
+            ipv4_prefix_len: 32,
+            ipv6_prefix_len: 64,
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                prune_threshold: MIN_PRUNE_THRESHOLD,
+            }),
+        }
+    }
+
+    /// Set how many leading bits of the source address identify a source.
+    /// The defaults are 32 for IPv4 and 64 for IPv6.
+    pub fn set_prefix_lens(&mut self, ipv4_prefix_len: u8, ipv6_prefix_len: u8) {
+        self.ipv4_prefix_len = ipv4_prefix_len.min(32);
+        self.ipv6_prefix_len = ipv6_prefix_len.min(128);
+    }
+
+    /// Forget all sources
+    pub fn clear(&self) {
+        self.buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map
+            .clear();
+    }
+
+    fn source_of(&self, ip: IpAddr) -> IpAddr {
+        let ip = ip.to_canonical();
+        let prefix_len = if ip.is_ipv4() {
+            self.ipv4_prefix_len
+        } else {
+            self.ipv6_prefix_len
+        };
+        Cidr::new(ip, prefix_len).map_or(ip, |cidr| cidr.addr())
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.updated = now;
+    }
+}
+
+impl Approver for RateLimitApprover {
+    fn is_client_allowed(&self, s: SocketAddr) -> Approval {
+        self.approval_at(s.ip(), Instant::now())
+    }
+}
+
+impl RateLimitApprover {
+    // Take a token for `ip` if it has one, as of `now`
+    fn approval_at(&self, ip: IpAddr, now: Instant) -> Approval {
+        let source = self.source_of(ip);
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Buckets that have refilled completely are no different from
+        // missing ones, so drop them once the map gets large
+        if buckets.map.len() >= buckets.prune_threshold {
+            buckets.map.retain(|_, bucket| {
+                let mut bucket = *bucket;
+                self.refill(&mut bucket, now);
+                bucket.tokens < self.burst
+            });
+            buckets.prune_threshold = (buckets.map.len() * 2).max(MIN_PRUNE_THRESHOLD);
+        }
+
+        let bucket = buckets.map.entry(source).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        self.refill(bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Approval::Approve
+        } else {
+            Approval::SilentlyRefuse
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn burst_is_exhausted() {
+        let limiter = RateLimitApprover::new(3, Duration::from_secs(30));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.approval_at(ip("192.0.2.1"), now), Approval::Approve);
+        }
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.1"), now),
+            Approval::SilentlyRefuse
+        );
+
+        // Other sources have their own budget
+        assert_eq!(limiter.approval_at(ip("192.0.2.2"), now), Approval::Approve);
+    }
+
+    #[test]
+    fn refills_over_time() {
+        // One token every 10 seconds
+        let limiter = RateLimitApprover::new(3, Duration::from_secs(30));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.approval_at(ip("192.0.2.1"), start),
+                Approval::Approve
+            );
+        }
+
+        let later = start + Duration::from_secs(9);
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.1"), later),
+            Approval::SilentlyRefuse
+        );
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.1"), later),
+            Approval::Approve
+        );
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.1"), later),
+            Approval::SilentlyRefuse
+        );
+
+        // Never more than the burst, however long it has been
+        let much_later = start + Duration::from_secs(1000);
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.approval_at(ip("192.0.2.1"), much_later),
+                Approval::Approve
+            );
+        }
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.1"), much_later),
+            Approval::SilentlyRefuse
+        );
+    }
+
+    #[test]
+    fn ipv6_slash_64_shares_a_bucket() {
+        let limiter = RateLimitApprover::new(2, Duration::from_secs(30));
+        let now = Instant::now();
+        assert_eq!(
+            limiter.approval_at(ip("2001:db8::1"), now),
+            Approval::Approve
+        );
+        assert_eq!(
+            limiter.approval_at(ip("2001:db8::ffff:2"), now),
+            Approval::Approve
+        );
+        assert_eq!(
+            limiter.approval_at(ip("2001:db8::3"), now),
+            Approval::SilentlyRefuse
+        );
+
+        // A different /64
+        assert_eq!(
+            limiter.approval_at(ip("2001:db8:0:1::1"), now),
+            Approval::Approve
+        );
+
+        // IPv4-mapped addresses count as their IPv4 address
+        assert_eq!(limiter.approval_at(ip("192.0.2.1"), now), Approval::Approve);
+        assert_eq!(
+            limiter.approval_at(ip("::ffff:192.0.2.1"), now),
+            Approval::Approve
+        );
+        assert_eq!(
+            limiter.approval_at(ip("::ffff:192.0.2.1"), now),
+            Approval::SilentlyRefuse
+        );
+    }
+
+    #[test]
+    fn prune_keeps_buckets_that_are_not_full() {
+        // One token every 5 seconds
+        let limiter = RateLimitApprover::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+
+        // One source uses both its tokens, and enough others to reach the
+        // prune threshold use one each
+        let busy = ip("192.0.2.1");
+        assert_eq!(limiter.approval_at(busy, start), Approval::Approve);
+        assert_eq!(limiter.approval_at(busy, start), Approval::Approve);
+        for i in 1..u32::try_from(MIN_PRUNE_THRESHOLD).unwrap() {
+            let source = IpAddr::from((0x0a00_0000 + i).to_be_bytes());
+            assert_eq!(limiter.approval_at(source, start), Approval::Approve);
+        }
+
+        // By now the others are full again but the busy source is not
+        let later = start + Duration::from_secs(5);
+        assert_eq!(
+            limiter.approval_at(ip("192.0.2.2"), later),
+            Approval::Approve
+        );
+        {
+            let buckets = limiter.buckets.lock().unwrap();
+            assert_eq!(buckets.map.len(), 2);
+            assert!(buckets.map.contains_key(&busy));
+        }
+
+        // and it still has only the one token it regained
+        assert_eq!(limiter.approval_at(busy, later), Approval::Approve);
+        assert_eq!(limiter.approval_at(busy, later), Approval::SilentlyRefuse);
+    }
+}
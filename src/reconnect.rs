@@ -0,0 +1,535 @@
+use crate::channel::Channel;
+use crate::client::{Client, ClientConfig};
+use crate::close_code::CloseCode;
+use crate::error::{Error, InnerError};
+use futures_core::Stream;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+
+/// How long a `ReconnectingClient` waits between attempts to connect
+///
+/// The delay starts at the initial delay and is multiplied after every failed
+/// attempt, up to the maximum delay. With jitter, each delay is randomly
+/// shortened by up to half, so that many clients dropped at once do not all
+/// come back at once.
+///
+/// ```no_run
+/// use mosaic_net::Backoff;
+/// use std::time::Duration;
+///
+/// let backoff = Backoff::new()
+///     .initial_delay(Duration::from_secs(1))
+///     .max_delay(Duration::from_secs(300));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: u32,
+    jitter: bool,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2,
+            jitter: true,
+        }
+    }
+}
+
+impl Backoff {
+    /// Create a `Backoff` starting at half a second, doubling up to 30 seconds,
+    /// with jitter
+    #[must_use]
+    pub fn new() -> Backoff {
+        Backoff::default()
+    }
+
+    /// The delay before the first attempt to reconnect
+    #[must_use]
+    pub fn initial_delay(mut self, delay: Duration) -> Backoff {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// The longest delay between attempts
+    #[must_use]
+    pub fn max_delay(mut self, delay: Duration) -> Backoff {
+        self.max_delay = delay;
+        self
+    }
+
+    /// What the delay is multiplied by after each failed attempt. This is at
+    /// least 1, so delays never shrink.
+    #[must_use]
+    pub fn multiplier(mut self, multiplier: u32) -> Backoff {
+        self.multiplier = multiplier.max(1);
+        self
+    }
+
+    /// Whether to randomly shorten each delay by up to half
+    #[must_use]
+    pub fn jitter(mut self, jitter: bool) -> Backoff {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay before attempt number `attempt` (starting at 1) to reconnect
+    #[must_use]
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.initial_delay;
+        for _ in 1..attempt {
+            if delay >= self.max_delay || self.multiplier <= 1 {
+                break;
+            }
+            delay = delay.saturating_mul(self.multiplier);
+        }
+        let delay = delay.min(self.max_delay);
+
+        if !self.jitter {
+            return delay;
+        }
+        // In u128, as half of a very long delay may not fit in a u64.
+        //
+        // Hashing nothing with a fresh `RandomState` is a deliberate stand-in
+        // for a RNG: its keys are seeded from the OS once per thread and
+        // change with every `RandomState`, which is random enough to spread
+        // reconnects out, without depending on a RNG crate.
+        let half = delay.as_nanos() / 2;
+        let random = u128::from(RandomState::new().build_hasher().finish());
+        let shorten = u64::try_from(random % (half + 1)).unwrap_or(u64::MAX);
+        delay.saturating_sub(Duration::from_nanos(shorten))
+    }
+}
+
+/// The state of a `ReconnectingClient`, as reported by `ConnectionEvents`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// Trying to connect. This is attempt number `n` since the last
+    /// successful connection.
+    Connecting(u32),
+
+    /// Connected, and the `OnConnect` callback has been started
+    Connected,
+
+    /// The attempt to connect failed, with the peer's close code if it sent one
+    ConnectFailed(Option<CloseCode>),
+
+    /// The connection was lost, with the peer's close code if it sent one
+    Disconnected(Option<CloseCode>),
+
+    /// Waiting this long before trying again
+    BackingOff(Duration),
+
+    /// Closed by `ReconnectingClient::close`. No more events follow.
+    Closed,
+
+    /// Stopped trying, because the attempt to connect failed in a way that
+    /// trying again cannot fix. No more events follow.
+    GaveUp(GiveUpReason),
+}
+
+/// Why a `ReconnectingClient` stopped trying to connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GiveUpReason {
+    /// The server presented a key other than the one known for it. Someone
+    /// may be impersonating the server, or it may have changed its key.
+    ServerKeyMismatch,
+
+    /// The `ClientConfig` can never connect, such as when its host is
+    /// invalid or its transport is unsupported
+    InvalidConfig,
+}
+
+impl GiveUpReason {
+    // Why retrying after `error` is pointless, if it is
+    fn of(error: &InnerError) -> Option<GiveUpReason> {
+        match error {
+            InnerError::ServerKeyMismatch(..) => Some(GiveUpReason::ServerKeyMismatch),
+            InnerError::AltTls(_)
+            | InnerError::InvalidHost(_)
+            | InnerError::InvalidHostPort(_)
+            | InnerError::InvalidServerLocator(_)
+            | InnerError::NoInitialCipherSuite(_)
+            | InnerError::NotIdempotent
+            | InnerError::Tls(_)
+            | InnerError::UnsupportedTransport(_)
+            | InnerError::ConnectError(
+                quinn::ConnectError::InvalidServerName(_)
+                | quinn::ConnectError::InvalidRemoteAddress(_)
+                | quinn::ConnectError::NoDefaultClientConfig
+                | quinn::ConnectError::UnsupportedVersion,
+            ) => Some(GiveUpReason::InvalidConfig),
+            _ => None,
+        }
+    }
+}
+
+/// Something to do every time a `ReconnectingClient` (re)connects, such as
+/// re-establishing subscriptions.
+///
+/// This is implemented for any `Fn(Arc<Client>) -> impl Future<Output = ()>`.
+pub trait OnConnect: Send + Sync + 'static {
+    /// Called with the new `Client` after every successful connection. The
+    /// connection is not watched for loss until this returns.
+    fn on_connect(&self, client: Arc<Client>) -> impl Future<Output = ()> + Send;
+}
+
+impl<F, Fut> OnConnect for F
+where
+    F: Fn(Arc<Client>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    fn on_connect(&self, client: Arc<Client>) -> impl Future<Output = ()> + Send {
+        self(client)
+    }
+}
+
+/// A stream of `ConnectionState` changes of a `ReconnectingClient`
+#[derive(Debug)]
+pub struct ConnectionEvents {
+    rx: UnboundedReceiver<ConnectionState>,
+}
+
+impl Stream for ConnectionEvents {
+    type Item = ConnectionState;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Current {
+    Down,
+    Up(Arc<Client>),
+    Closed,
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: ClientConfig,
+    backoff: Backoff,
+    current: watch::Sender<Current>,
+    state: Mutex<ConnectionState>,
+    subscribers: Mutex<Vec<UnboundedSender<ConnectionState>>>,
+    closing: Notify,
+    close_reason: Mutex<(CloseCode, Vec<u8>)>,
+}
+
+impl Shared {
+    fn emit(&self, state: ConnectionState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|tx| tx.send(state).is_ok());
+    }
+
+    async fn finish(&self, client: Option<Arc<Client>>, last: ConnectionState) {
+        let _ = self.current.send_replace(Current::Closed);
+        let (code, reason) = self
+            .close_reason
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(client) = client {
+            match Arc::try_unwrap(client) {
+                Ok(client) => client.close(code, &reason).await,
+                // Someone still holds the Client, so we can't wait for it
+                Err(client) => client.inner().close(code.into(), &reason),
+            }
+        }
+        self.emit(last);
+    }
+}
+
+/// A `Client` that redials whenever its connection is lost
+///
+/// Connection attempts are spaced out according to a `Backoff`. Watch
+/// `ReconnectingClient::events` to follow along, and supply an `OnConnect`
+/// to re-establish subscriptions on every new connection.
+///
+/// `Channel`s belong to a single connection, so they end when it is lost.
+/// Open new ones after reconnecting.
+#[derive(Debug)]
+pub struct ReconnectingClient {
+    shared: Arc<Shared>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl ReconnectingClient {
+    /// Start connecting to the server described by `config`, and keep
+    /// reconnecting until closed, or until an attempt fails in a way that
+    /// trying again cannot fix (see `ConnectionState::GaveUp`).
+    ///
+    /// This must be called from within a tokio runtime.
+    #[must_use]
+    pub fn new(config: ClientConfig, backoff: Backoff) -> ReconnectingClient {
+        ReconnectingClient::with_on_connect(config, backoff, |_| std::future::ready(()))
+    }
+
+    /// Like `ReconnectingClient::new`, running `on_connect` after every
+    /// successful connection.
+    ///
+    /// This must be called from within a tokio runtime.
+    #[must_use]
+    pub fn with_on_connect<O: OnConnect>(
+        config: ClientConfig,
+        backoff: Backoff,
+        on_connect: O,
+    ) -> ReconnectingClient {
+        let shared = Arc::new(Shared {
+            config,
+            backoff,
+            current: watch::Sender::new(Current::Down),
+            state: Mutex::new(ConnectionState::Connecting(1)),
+            subscribers: Mutex::new(Vec::new()),
+            closing: Notify::new(),
+            close_reason: Mutex::new((CloseCode::Normal, Vec::new())),
+        });
+        let task = tokio::spawn(supervise(shared.clone(), on_connect));
+        ReconnectingClient {
+            shared,
+            task: Some(task),
+        }
+    }
+
+    /// The most recent `ConnectionState`
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        *self
+            .shared
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Subscribe to `ConnectionState` changes from now on
+    #[must_use]
+    pub fn events(&self) -> ConnectionEvents {
+        let (tx, rx) = unbounded_channel();
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(tx);
+        ConnectionEvents { rx }
+    }
+
+    /// The current `Client`, if connected
+    #[must_use]
+    pub fn client(&self) -> Option<Arc<Client>> {
+        match &*self.shared.current.borrow() {
+            Current::Up(client) => Some(client.clone()),
+            _ => None,
+        }
+    }
+
+    /// Wait until connected, and return the `Client`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the `ReconnectingClient` has been closed or has given up
+    pub async fn connected(&self) -> Result<Arc<Client>, Error> {
+        let mut rx = self.shared.current.subscribe();
+        let current = rx
+            .wait_for(|c| !matches!(c, Current::Down))
+            .await
+            .map_err(|_| InnerError::ShuttingDown.into_err())?;
+        match &*current {
+            Current::Up(client) => Ok(client.clone()),
+            _ => Err(InnerError::ShuttingDown.into()),
+        }
+    }
+
+    /// Wait until connected, and open a new `Channel`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the `ReconnectingClient` has been closed or has given up, or
+    /// if there was a QUIC `open_bi()` problem
+    pub async fn new_channel(&self) -> Result<Channel, Error> {
+        self.connected().await?.new_channel().await
+    }
+
+    /// Stop reconnecting, and close down the current connection (if any)
+    /// gracefully.
+    ///
+    /// `reason` will be truncated if it does not fit in a single packet
+    pub async fn close(mut self, code: CloseCode, reason: &[u8]) {
+        *self
+            .shared
+            .close_reason
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = (code, reason.to_vec());
+        self.shared.closing.notify_one();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for ReconnectingClient {
+    fn drop(&mut self) {
+        if self.task.is_some() {
+            self.shared.closing.notify_one();
+        }
+    }
+}
+
+async fn supervise<O: OnConnect>(shared: Arc<Shared>, on_connect: O) {
+    let mut attempt: u32 = 0;
+    loop {
+        attempt = attempt.saturating_add(1);
+        shared.emit(ConnectionState::Connecting(attempt));
+        let result = tokio::select! {
+            result = shared.config.client(None) => result,
+            () = shared.closing.notified() => break,
+        };
+
+        match result {
+            Ok(client) => {
+                attempt = 0;
+                let client = Arc::new(client);
+                let _ = shared.current.send_replace(Current::Up(client.clone()));
+                shared.emit(ConnectionState::Connected);
+
+                let error = tokio::select! {
+                    error = async {
+                        on_connect.on_connect(client.clone()).await;
+                        client.inner().closed().await
+                    } => error,
+                    () = shared.closing.notified() => {
+                        shared.finish(Some(client), ConnectionState::Closed).await;
+                        return;
+                    }
+                };
+
+                let _ = shared.current.send_replace(Current::Down);
+                shared.emit(ConnectionState::Disconnected(
+                    Error::from(error).close_code(),
+                ));
+            }
+            Err(e) => {
+                if let Some(reason) = GiveUpReason::of(&e.inner) {
+                    shared.finish(None, ConnectionState::GaveUp(reason)).await;
+                    return;
+                }
+                shared.emit(ConnectionState::ConnectFailed(e.close_code()));
+            }
+        }
+
+        let delay = shared.backoff.delay(attempt.max(1));
+        shared.emit(ConnectionState::BackingOff(delay));
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = shared.closing.notified() => break,
+        }
+    }
+    shared.finish(None, ConnectionState::Closed).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn delay_grows_by_the_multiplier_up_to_the_max() {
+        let backoff = Backoff::new()
+            .initial_delay(SECOND)
+            .max_delay(SECOND * 10)
+            .jitter(false);
+        let delays: Vec<u64> = (1..=6).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+
+        let backoff = backoff.multiplier(3);
+        let delays: Vec<u64> = (1..=4).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 3, 9, 10]);
+    }
+
+    #[test]
+    fn multiplier_of_zero_or_one_keeps_the_initial_delay() {
+        for multiplier in [0, 1] {
+            let backoff = Backoff::new()
+                .initial_delay(SECOND)
+                .multiplier(multiplier)
+                .jitter(false);
+            for attempt in [1, 2, 10, u32::MAX] {
+                assert_eq!(backoff.delay(attempt), SECOND);
+            }
+        }
+    }
+
+    #[test]
+    fn very_large_attempt_is_capped() {
+        let backoff = Backoff::new().jitter(false);
+        assert_eq!(backoff.delay(u32::MAX), SECOND * 30);
+
+        // Nothing overflows, even with no cap to speak of
+        let backoff = Backoff::new()
+            .initial_delay(Duration::MAX / 2)
+            .max_delay(Duration::MAX)
+            .multiplier(u32::MAX)
+            .jitter(false);
+        assert_eq!(backoff.delay(u32::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn jitter_shortens_by_at_most_half() {
+        let backoff = Backoff::new().initial_delay(SECOND).max_delay(SECOND * 10);
+        for _ in 0..1000 {
+            let delay = backoff.delay(3);
+            assert!(delay >= SECOND * 2 && delay <= SECOND * 4, "{delay:?}");
+        }
+
+        let backoff = Backoff::new()
+            .initial_delay(Duration::MAX)
+            .max_delay(Duration::MAX);
+        for _ in 0..1000 {
+            assert!(backoff.delay(1) >= Duration::MAX / 2);
+        }
+
+        let backoff = Backoff::new().initial_delay(Duration::ZERO);
+        assert_eq!(backoff.delay(1), Duration::ZERO);
+    }
+
+    #[test]
+    fn gives_up_only_when_retrying_cannot_help() {
+        let key = mosaic_core::SecretKey::generate().public();
+        let mismatch = InnerError::ServerKeyMismatch("example.com:443".to_owned(), key, key);
+        assert_eq!(
+            GiveUpReason::of(&mismatch),
+            Some(GiveUpReason::ServerKeyMismatch)
+        );
+        assert_eq!(
+            GiveUpReason::of(&InnerError::NotIdempotent),
+            Some(GiveUpReason::InvalidConfig)
+        );
+        assert_eq!(
+            GiveUpReason::of(&InnerError::InvalidHost("a b".to_owned())),
+            Some(GiveUpReason::InvalidConfig)
+        );
+
+        assert_eq!(GiveUpReason::of(&InnerError::ConnectTimeout), None);
+        assert_eq!(
+            GiveUpReason::of(&InnerError::HostNotFound("example.com".to_owned())),
+            None
+        );
+        let lost = quinn::ConnectionError::TimedOut;
+        assert_eq!(GiveUpReason::of(&InnerError::ConnectionError(lost)), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::{Error, InnerError};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{PoisonError, RwLock};
+
+/// A boxed future returned by a `Resolver`
+pub type ResolveFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<SocketAddr>, Error>> + Send + 'a>>;
+
+/// Something that resolves host names into socket addresses
+///
+/// `ClientConfig::with_host` uses a `SystemResolver` unless you set its
+/// `resolver`. Tests can use a `StaticResolver`.
+pub trait Resolver: std::fmt::Debug + Send + Sync {
+    /// Resolve `host` into the socket addresses it may be reached at on `port`
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+/// A `Resolver` that uses the operating system's resolver (usually DNS and
+/// the hosts file)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host, port)).await?;
+            Ok(addrs.collect())
+        })
+    }
+}
+
+/// A `Resolver` that looks host names up in a fixed table
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    hosts: RwLock<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl StaticResolver {
+    /// Create an empty `StaticResolver`
+    #[must_use]
+    pub fn new() -> StaticResolver {
+        StaticResolver::default()
+    }
+
+    /// Make `host` resolve to `addrs`, replacing anything it resolved to before
+    pub fn insert(&self, host: &str, addrs: Vec<IpAddr>) {
+        let _ = self
+            .hosts
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(host.to_ascii_lowercase(), addrs);
+    }
+
+    /// Make `host` no longer resolve
+    pub fn remove(&self, host: &str) {
+        let _ = self
+            .hosts
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&host.to_ascii_lowercase());
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        let result = self
+            .hosts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&host.to_ascii_lowercase())
+            .map(|addrs| addrs.iter().map(|ip| SocketAddr::new(*ip, port)).collect())
+            .ok_or_else(|| InnerError::HostNotFound(host.to_owned()).into());
+        Box::pin(std::future::ready(result))
+    }
+}
+
+/// Split `host:port` into its parts. IPv6 addresses must be in brackets, as
+/// in `[2001:db8::1]:443`, and nothing else may be.
+pub(crate) fn split_host_port(s: &str) -> Result<(String, u16), Error> {
+    let invalid = || InnerError::InvalidHostPort(s.to_owned()).into_err();
+    let (host, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+    let host = match host.strip_prefix('[') {
+        Some(h) => {
+            let h = h.strip_suffix(']').ok_or_else(invalid)?;
+            let _: Ipv6Addr = h.parse().map_err(|_| invalid())?;
+            h
+        }
+        None if host.contains(':') => return Err(invalid()),
+        None => host,
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    Ok((host.to_owned(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_host_and_port() {
+        let split = |s| split_host_port(s).unwrap();
+        assert_eq!(
+            split("relay.example.com:8081"),
+            ("relay.example.com".to_owned(), 8081)
+        );
+        assert_eq!(split("192.0.2.1:443"), ("192.0.2.1".to_owned(), 443));
+        assert_eq!(split("[2001:db8::1]:443"), ("2001:db8::1".to_owned(), 443));
+        assert_eq!(split("[::1]:0"), ("::1".to_owned(), 0));
+    }
+
+    #[test]
+    fn rejects_invalid_host_port() {
+        for s in [
+            "relay.example.com",
+            "relay.example.com:",
+            "relay.example.com:65536",
+            ":443",
+            "[]:443",
+            "2001:db8::1:443",
+            "[2001:db8::1:443",
+            "2001:db8::1]:443",
+            "[relay.example.com]:443",
+            "[192.0.2.1]:443",
+        ] {
+            assert!(
+                matches!(
+                    split_host_port(s).map_err(|e| e.inner),
+                    Err(InnerError::InvalidHostPort(_))
+                ),
+                "{s}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn static_resolver_looks_up_table() {
+        let resolver = StaticResolver::new();
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        resolver.insert("Relay.Example.com", vec![v6, v4]);
+
+        let addrs = resolver.resolve("relay.example.COM", 8081).await.unwrap();
+        assert_eq!(
+            addrs,
+            vec![SocketAddr::new(v6, 8081), SocketAddr::new(v4, 8081)]
+        );
+
+        resolver.insert("relay.example.com", vec![v4]);
+        let addrs = resolver.resolve("relay.example.com", 443).await.unwrap();
+        assert_eq!(addrs, vec![SocketAddr::new(v4, 443)]);
+
+        resolver.remove("relay.example.com");
+        assert!(matches!(
+            resolver.resolve("relay.example.com", 443).await.map_err(|e| e.inner),
+            Err(InnerError::HostNotFound(h)) if h == "relay.example.com"
+        ));
+    }
+}
@@ -1,12 +1,116 @@
 use crate::ALPN_QUIC_MOSAIC;
-use crate::channel::Channel;
+use crate::channel::{Channel, ChannelOptions};
+use crate::close_code::CloseCode;
 use crate::error::{Error, InnerError};
+use crate::identity::{RotatingKeyResolver, peer_public_key};
+use crate::limits::{ConnectionLimits, ConnectionSlot, ConnectionTracker};
+use crate::stats::{ChannelCounter, ConnectionStats};
+use crate::transport::TransportOptions;
 use mosaic_core::{PublicKey, SecretKey};
-use quinn::ServerConfig as QuinnServerConfig;
+use quinn::{ServerConfig as QuinnServerConfig, TransportConfig, VarInt};
 use rustls::ServerConfig as TlsServerConfig;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// The default maximum number of simultaneous `Channel`s per `ClientConnection`
+pub const DEFAULT_MAX_CHANNELS: u32 = 100;
+
+/// When to require stateless retry of new clients
+///
+/// Stateless retry makes a client prove that it controls the IP address and
+/// port it claims to be connecting from before we spend any resources on it.
+/// This significantly reduces the effect of denial of service attacks, but
+/// costs the client one round trip the first time it connects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RetryPolicy {
+    /// Always require stateless retry of unvalidated clients
+    #[default]
+    Always,
+
+    /// Never require stateless retry
+    Never,
+
+    /// Require stateless retry of unvalidated clients only while more than
+    /// this many handshakes are in progress
+    UnderLoad(usize),
+}
+
+/// The minimum length of `TokenKey::Shared` key material, in bytes
+pub const MIN_TOKEN_KEY_LEN: usize = 32;
+
+// HKDF salt used to derive the token key from key material
+const TOKEN_KEY_SALT: &[u8] = b"mosaic-net address validation token key";
+
+/// The key used to seal stateless retry and address validation tokens
+///
+/// A client that was sent a retry, or that was given a token on an earlier
+/// connection, presents the token back to us. We can only validate it if we
+/// still have the key that sealed it. A `Random` key is lost when the `Server`
+/// restarts and is not known by other nodes behind the same load balancer, so
+/// such clients are made to retry again.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum TokenKey {
+    /// A fresh random key each time the `Server` starts
+    #[default]
+    Random,
+
+    /// Derive the key from the server's `SecretKey`. This survives restarts
+    /// and is shared by every node running with the same `SecretKey`.
+    FromSecretKey,
+
+    /// Derive the key from this key material, which must be at least
+    /// `MIN_TOKEN_KEY_LEN` bytes. Share it between nodes that should accept
+    /// each other's tokens.
+    Shared(Vec<u8>),
+}
+
+impl TokenKey {
+    /// Load `Shared` key material from a file. The whole file is the key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or is shorter than `MIN_TOKEN_KEY_LEN`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TokenKey, Error> {
+        let key = std::fs::read(path)?;
+        if key.len() < MIN_TOKEN_KEY_LEN {
+            return Err(InnerError::TokenKeyTooShort(key.len()).into());
+        }
+        Ok(TokenKey::Shared(key))
+    }
+
+    // The quinn token key, or None to let quinn pick a random one
+    fn prk(&self, secret_key: &SecretKey) -> Result<Option<ring::hkdf::Prk>, Error> {
+        let salt = ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, TOKEN_KEY_SALT);
+        match self {
+            TokenKey::Random => Ok(None),
+            TokenKey::FromSecretKey => {
+                Ok(Some(salt.extract(&secret_key.to_signing_key().to_bytes())))
+            }
+            TokenKey::Shared(key) => {
+                if key.len() < MIN_TOKEN_KEY_LEN {
+                    return Err(InnerError::TokenKeyTooShort(key.len()).into());
+                }
+                Ok(Some(salt.extract(key)))
+            }
+        }
+    }
+}
+
+// Don't leak key material into logs
+impl std::fmt::Debug for TokenKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKey::Random => write!(f, "Random"),
+            TokenKey::FromSecretKey => write!(f, "FromSecretKey"),
+            TokenKey::Shared(key) => write!(f, "Shared([{} bytes])", key.len()),
+        }
+    }
+}
 
 /// A configuration for creating a `Server`
 #[derive(Debug, Clone)]
@@ -17,7 +121,42 @@ pub struct ServerConfig {
     /// Socket address to bind to
     pub socket_addr: SocketAddr,
 
-    quinn: QuinnServerConfig,
+    /// Options applied to every `Channel` of every `ClientConnection`
+    pub channel_options: ChannelOptions,
+
+    /// Caps on simultaneous `ClientConnection`s
+    pub connection_limits: ConnectionLimits,
+
+    /// Maximum simultaneous `Channel`s on a `ClientConnection` from an
+    /// anonymous peer
+    pub max_channels_anonymous: u32,
+
+    /// Maximum simultaneous `Channel`s on a `ClientConnection` from an
+    /// authenticated peer
+    pub max_channels_authenticated: u32,
+
+    /// Tuning for the QUIC transport
+    pub transport: TransportOptions,
+
+    /// When to require stateless retry of new clients
+    pub retry_policy: RetryPolicy,
+
+    /// The key that seals stateless retry and address validation tokens
+    pub token_key: TokenKey,
+
+    /// Keys this server used to have, each presented until the given time to
+    /// clients that still ask for it. Clients ask for the first key they
+    /// accept, so give them the new key as a second accepted key, then as
+    /// their first, before the transition window ends.
+    pub previous_keys: Vec<(SecretKey, SystemTime)>,
+
+    /// Whether to accept 0-RTT early data from clients resuming a session.
+    /// Clients only send idempotent messages early, and each session ticket
+    /// is accepted only once by this `Server`, but other servers sharing the
+    /// same keys will not know that.
+    pub early_data: bool,
+
+    tls: Arc<TlsServerConfig>,
 }
 
 impl ServerConfig {
@@ -27,7 +166,6 @@ impl ServerConfig {
     ///
     /// Errors on numerous things that should not occur based on input, but might occur
     /// as software changes over time.
-    #[allow(clippy::missing_panics_doc)]
     pub fn new(secret_key: SecretKey, socket_addr: SocketAddr) -> Result<ServerConfig, Error> {
         // Create a Mosaic-compliant self-signed TLS identity
         let (certificate_der, private_key_der) = alt_tls::self_signed_tls_identity(
@@ -60,23 +198,60 @@ impl ServerConfig {
             Arc::new(server_config)
         };
 
-        // Create a QUIC server configuration from the rustls TLS configuration
-        let qsc = Arc::new(quinn_proto::crypto::rustls::QuicServerConfig::try_from(
-            rustls_server_config,
-        )?);
-        let mut quinn_server_config = QuinnServerConfig::with_crypto(qsc);
-
-        // Tweak the QUIC server configuration
-        let transport_config = Arc::get_mut(&mut quinn_server_config.transport).unwrap();
-        let _ = transport_config.max_concurrent_uni_streams(0_u8.into());
-
         Ok(ServerConfig {
             secret_key,
             socket_addr,
-            quinn: quinn_server_config,
+            channel_options: ChannelOptions::default(),
+            connection_limits: ConnectionLimits::default(),
+            max_channels_anonymous: DEFAULT_MAX_CHANNELS,
+            max_channels_authenticated: DEFAULT_MAX_CHANNELS,
+            transport: TransportOptions::default(),
+            retry_policy: RetryPolicy::default(),
+            token_key: TokenKey::default(),
+            previous_keys: Vec::new(),
+            early_data: false,
+            tls: rustls_server_config,
         })
     }
 
+    // The QUIC server configuration with our transport and TLS settings applied
+    fn quinn_config(&self) -> Result<QuinnServerConfig, Error> {
+        let mut transport_config = TransportConfig::default();
+        self.transport.apply(&mut transport_config);
+        let _ = transport_config.max_concurrent_uni_streams(0_u8.into());
+
+        // We don't know if the peer is authenticated until after the handshake,
+        // so start with the lower limit. `IncomingClient::accept` raises it.
+        let _ = transport_config.max_concurrent_bidi_streams(VarInt::from_u32(
+            self.max_channels_anonymous
+                .min(self.max_channels_authenticated),
+        ));
+
+        let mut rustls_server_config = (*self.tls).clone();
+        if !self.previous_keys.is_empty() {
+            rustls_server_config.cert_resolver = Arc::new(RotatingKeyResolver::new(
+                &self.secret_key,
+                &self.previous_keys,
+                &alt_tls::provider(),
+            )?);
+        }
+        if self.early_data {
+            // QUIC requires exactly this value when early data is enabled
+            rustls_server_config.max_early_data_size = u32::MAX;
+        }
+
+        // Create a QUIC server configuration from the rustls TLS configuration
+        let qsc = Arc::new(quinn_proto::crypto::rustls::QuicServerConfig::try_from(
+            Arc::new(rustls_server_config),
+        )?);
+        let mut quinn_server_config = QuinnServerConfig::with_crypto(qsc);
+        let _ = quinn_server_config.transport_config(Arc::new(transport_config));
+        if let Some(prk) = self.token_key.prk(&self.secret_key)? {
+            let _ = quinn_server_config.token_key(Arc::new(prk));
+        }
+        Ok(quinn_server_config)
+    }
+
     /// Retrieve the socket address
     #[must_use]
     pub fn socket_addr(&self) -> SocketAddr {
@@ -92,6 +267,8 @@ pub struct Server {
     config: ServerConfig,
     endpoint: quinn::Endpoint,
     shutting_down: AtomicBool,
+    connections: Arc<ConnectionTracker>,
+    handshakes: Arc<AtomicUsize>,
 }
 
 impl Server {
@@ -101,17 +278,23 @@ impl Server {
     ///
     /// Errors if the server could not be setup.
     pub fn new(config: ServerConfig) -> Result<Server, Error> {
-        let endpoint = quinn::Endpoint::server(config.quinn.clone(), config.socket_addr)?;
+        let endpoint = quinn::Endpoint::server(config.quinn_config()?, config.socket_addr)?;
+        let connections = Arc::new(ConnectionTracker::new(config.connection_limits));
         Ok(Self {
             config,
             endpoint,
             shutting_down: AtomicBool::new(false),
+            connections,
+            handshakes: Arc::new(AtomicUsize::new(0)),
         })
     }
 
     /// Accept a new connection. This returns as soon as it can so that the
     /// thread that calls it can get on with other clients.
     ///
+    /// Clients that need stateless retry according to the `RetryPolicy` are
+    /// sent a retry here and not returned. They come back validated.
+    ///
     /// # Errors
     ///
     /// Errors if the endpoint is closed
@@ -120,11 +303,47 @@ impl Server {
             return Err(InnerError::ShuttingDown.into());
         }
 
-        self.endpoint
-            .accept()
-            .await
-            .map(IncomingClient)
-            .ok_or::<Error>(InnerError::EndpointIsClosed.into())
+        loop {
+            let Some(mut incoming) = self.endpoint.accept().await else {
+                return Err(InnerError::EndpointIsClosed.into());
+            };
+
+            if !incoming.remote_address_validated() && self.retry_required() {
+                match incoming.retry() {
+                    Ok(()) => continue,
+                    Err(e) => incoming = e.into_incoming(),
+                }
+            }
+
+            return Ok(IncomingClient {
+                incoming,
+                channel_options: self.config.channel_options,
+                max_channels_anonymous: self.config.max_channels_anonymous,
+                max_channels_authenticated: self.config.max_channels_authenticated,
+                connections: self.connections.clone(),
+                handshake: HandshakeGuard::new(&self.handshakes),
+            });
+        }
+    }
+
+    fn retry_required(&self) -> bool {
+        match self.config.retry_policy {
+            RetryPolicy::Always => true,
+            RetryPolicy::Never => false,
+            RetryPolicy::UnderLoad(n) => self.handshake_count() > n,
+        }
+    }
+
+    /// The number of `IncomingClient`s that have not yet finished accepting
+    #[must_use]
+    pub fn handshake_count(&self) -> usize {
+        self.handshakes.load(Ordering::Relaxed)
+    }
+
+    /// The number of live `ClientConnection`s
+    #[must_use]
+    pub fn connection_count(&self) -> usize {
+        self.connections.total()
     }
 
     /// If the server is shutting down
@@ -133,7 +352,7 @@ impl Server {
     }
 
     /// Shut down gracefully.
-    pub async fn shut_down(&self, code: u32, reason: &[u8]) {
+    pub async fn shut_down(&self, code: CloseCode, reason: &[u8]) {
         if !self.shutting_down.load(Ordering::Acquire) {
             self.shutting_down.store(true, Ordering::Release);
             self.endpoint.close(code.into(), reason);
@@ -176,6 +395,22 @@ pub trait Approver: Send + Sync {
     fn is_client_allowed(&self, s: SocketAddr) -> Approval;
 }
 
+/// An object that handles approval and rejection of clients asynchronously,
+/// for when the decision requires I/O such as a database lookup.
+/// This occurs before the TLS handshake, so it is based on `SocketAddr` only.
+///
+/// Every `Approver` is also an `AsyncApprover`.
+pub trait AsyncApprover: Send + Sync {
+    /// Should we allow this client to connect?
+    fn is_client_allowed_async(&self, s: SocketAddr) -> impl Future<Output = Approval> + Send;
+}
+
+impl<T: Approver + ?Sized> AsyncApprover for T {
+    fn is_client_allowed_async(&self, s: SocketAddr) -> impl Future<Output = Approval> + Send {
+        std::future::ready(self.is_client_allowed(s))
+    }
+}
+
 /// An `Approver` that always accepts
 #[derive(Debug, Clone, Copy)]
 pub struct AlwaysAllowedApprover;
@@ -186,54 +421,153 @@ impl Approver for AlwaysAllowedApprover {
     }
 }
 
+/// What an authenticated (or anonymous) peer is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Authorization {
+    /// Full access
+    Authorize,
+
+    /// Access is limited to reading. Enforcing this is up to the server
+    /// application, see `ClientConnection::is_read_only()`
+    ReadOnly,
+
+    /// Refuse, closing the connection with the given code
+    Refuse(CloseCode),
+}
+
+/// An object that handles authorization of clients.
+/// This occurs after the TLS handshake, so it is based on the peer's Mosaic
+/// `PublicKey` (`None` if they connected anonymously) as well as `SocketAddr`.
+pub trait PeerAuthorizer: Send + Sync {
+    /// What may this client do?
+    fn authorize(&self, peer: Option<PublicKey>, s: SocketAddr) -> Authorization;
+}
+
+/// An object that handles authorization of clients asynchronously, for when
+/// the decision requires I/O such as a database lookup.
+/// This occurs after the TLS handshake, so it is based on the peer's Mosaic
+/// `PublicKey` (`None` if they connected anonymously) as well as `SocketAddr`.
+///
+/// Every `PeerAuthorizer` is also an `AsyncPeerAuthorizer`.
+pub trait AsyncPeerAuthorizer: Send + Sync {
+    /// What may this client do?
+    fn authorize_async(
+        &self,
+        peer: Option<PublicKey>,
+        s: SocketAddr,
+    ) -> impl Future<Output = Authorization> + Send;
+}
+
+impl<T: PeerAuthorizer + ?Sized> AsyncPeerAuthorizer for T {
+    fn authorize_async(
+        &self,
+        peer: Option<PublicKey>,
+        s: SocketAddr,
+    ) -> impl Future<Output = Authorization> + Send {
+        std::future::ready(self.authorize(peer, s))
+    }
+}
+
+/// A `PeerAuthorizer` that always authorizes fully
+#[derive(Debug, Clone, Copy)]
+pub struct AlwaysAllowedAuthorizer;
+
+impl PeerAuthorizer for AlwaysAllowedAuthorizer {
+    fn authorize(&self, _: Option<PublicKey>, _: SocketAddr) -> Authorization {
+        Authorization::Authorize
+    }
+}
+
 /// An incoming client that is not fully accepted yet, but should probably be
 /// handled and awaited upon in in a separate task from the main server
 /// accepting thread
 #[derive(Debug)]
-pub struct IncomingClient(quinn::Incoming);
+pub struct IncomingClient {
+    incoming: quinn::Incoming,
+    channel_options: ChannelOptions,
+    max_channels_anonymous: u32,
+    max_channels_authenticated: u32,
+    connections: Arc<ConnectionTracker>,
+    handshake: HandshakeGuard,
+}
+
+// Counts an `IncomingClient` as a handshake in progress until dropped
+#[derive(Debug)]
+struct HandshakeGuard(Arc<AtomicUsize>);
+
+impl HandshakeGuard {
+    fn new(count: &Arc<AtomicUsize>) -> HandshakeGuard {
+        let _ = count.fetch_add(1, Ordering::Relaxed);
+        HandshakeGuard(count.clone())
+    }
+}
+
+impl Drop for HandshakeGuard {
+    fn drop(&mut self) {
+        let _ = self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 impl IncomingClient {
-    #[allow(clippy::doc_markdown)]
-    /// Accept (or reject) the incoming client based on the `approve` function
-    /// which allows you to block IP addresses.
+    /// Accept (or reject) the incoming client based on the `approver` which
+    /// allows you to block IP addresses. Every peer that completes the
+    /// handshake is fully authorized; use `accept_with_authorizer` to refuse
+    /// or restrict peers based on their public key.
     ///
-    /// Internally this requires stateless retry to verify that the client
-    /// actually controls the IP address and port it claims to be connecting
-    /// from, which requires a round-trip but significantly reduces the effect
-    /// of DoS attacks.
+    /// # Errors
+    ///
+    /// Errors if the remote address is not approved, if the `ConnectionLimits`
+    /// have been reached, or if there is a problem connecting.
+    pub async fn accept<A: AsyncApprover>(self, approver: &A) -> Result<ClientConnection, Error> {
+        self.accept_with_authorizer(approver, &AlwaysAllowedAuthorizer)
+            .await
+    }
+
+    /// Accept (or reject) the incoming client based on the `approver` which
+    /// allows you to block IP addresses, and then on the `authorizer` which
+    /// allows you to refuse or restrict peers based on their public key.
     ///
     /// # Errors
     ///
-    /// Errors if client does not perform stateless retry properly, if the
-    /// remote address is not approved, or if there is a problem connecting.
+    /// Errors if the remote address is not approved, if the peer is not
+    /// authorized (`InnerError::PeerNotAuthorized` with the code the
+    /// connection was closed with), if the `ConnectionLimits` have been
+    /// reached, or if there is a problem connecting.
     #[allow(clippy::missing_panics_doc)]
-    pub async fn accept<A: Approver>(self, approver: &A) -> Result<ClientConnection, Error> {
-        // We don't talk to brand new endpoints until they prove that they
-        // control the remote IP and PORT that the packet claims. This is
-        // called "stateless retry". The first connection they make must
-        // contain a DCID we recognize. This requires 1-RTT, but only the
-        // first time they connect to us (not having a token). It prevents
-        // certain kinds of security problems, at the cost of a RTT.
-        if !self.0.remote_address_validated() {
-            self.0.retry()?;
-            return Err(InnerError::StatelessRetryRequired.into());
-        }
-
-        let remote_socket_addr: SocketAddr = self.0.remote_address();
-
-        match approver.is_client_allowed(remote_socket_addr) {
+    pub async fn accept_with_authorizer<A: AsyncApprover, P: AsyncPeerAuthorizer>(
+        self,
+        approver: &A,
+        authorizer: &P,
+    ) -> Result<ClientConnection, Error> {
+        // Stateless retry (if the `RetryPolicy` called for it) was already
+        // handled by `Server::accept`. We count as a handshake in progress
+        // until we return.
+        let _handshake = self.handshake;
+
+        let remote_socket_addr: SocketAddr = self.incoming.remote_address();
+
+        match approver.is_client_allowed_async(remote_socket_addr).await {
             Approval::Approve => {}
             Approval::Refuse => {
-                self.0.refuse();
+                self.incoming.refuse();
                 return Err(InnerError::RemoteAddressNotApproved.into());
             }
             Approval::SilentlyRefuse => {
-                self.0.ignore();
+                self.incoming.ignore();
                 return Err(InnerError::RemoteAddressNotApproved.into());
             }
         }
 
-        let mut connecting = self.0.accept()?;
+        let mut slot = match self.connections.reserve(remote_socket_addr.ip()) {
+            Ok(slot) => slot,
+            Err(limit) => {
+                self.incoming.refuse();
+                return Err(InnerError::ConnectionLimitReached(limit).into());
+            }
+        };
+
+        let started = Instant::now();
+        let mut connecting = self.incoming.accept()?;
 
         // Verify ALPN
         match connecting
@@ -253,32 +587,50 @@ impl IncomingClient {
         }
 
         let connection = connecting.await?;
+        let handshake_duration = started.elapsed();
 
-        let mut peer: Option<PublicKey> = None;
-        if let Some(id) = connection.peer_identity() {
-            match id.downcast_ref::<Vec<rustls::pki_types::CertificateDer>>() {
-                Some(vec) => {
-                    for cert in vec {
-                        if let Ok(vk) = alt_tls::public_key_from_certificate_der(cert) {
-                            peer = Some(PublicKey::from_verifying_key(&vk));
-                        }
-                    }
-                }
-                None => panic!("Invalid downcast code"),
-            }
+        let peer = peer_public_key(&connection);
+
+        if let Some(pk) = peer
+            && let Err(limit) = slot.set_peer(pk)
+        {
+            connection.close(CloseCode::TooManyConnections.into(), b"");
+            return Err(InnerError::ConnectionLimitReached(limit).into());
         }
 
+        let read_only = match authorizer.authorize_async(peer, remote_socket_addr).await {
+            Authorization::Authorize => false,
+            Authorization::ReadOnly => true,
+            Authorization::Refuse(code) => {
+                connection.close(code.into(), b"");
+                return Err(InnerError::PeerNotAuthorized(code).into());
+            }
+        };
+
+        let max_channels = if peer.is_some() {
+            self.max_channels_authenticated
+        } else {
+            self.max_channels_anonymous
+        };
+        connection.set_max_concurrent_bi_streams(VarInt::from_u32(max_channels));
+
         Ok(ClientConnection {
             remote_socket_addr,
             inner: connection,
             peer,
+            read_only,
+            channel_options: self.channel_options,
+            channels: ChannelCounter::default(),
+            handshake_duration,
+            established: Instant::now(),
+            _slot: slot,
         })
     }
 
     /// Get at the inner `quinn::Incoming`
     #[must_use]
     pub fn inner(&self) -> &quinn::Incoming {
-        &self.0
+        &self.incoming
     }
 }
 
@@ -288,6 +640,14 @@ pub struct ClientConnection {
     inner: quinn::Connection,
     remote_socket_addr: SocketAddr,
     peer: Option<PublicKey>,
+    read_only: bool,
+    channel_options: ChannelOptions,
+    channels: ChannelCounter,
+    handshake_duration: Duration,
+    established: Instant,
+
+    // Counts this connection against the `ConnectionLimits` until dropped
+    _slot: ConnectionSlot,
 }
 
 impl ClientConnection {
@@ -309,16 +669,34 @@ impl ClientConnection {
         self.peer
     }
 
+    /// Whether the `PeerAuthorizer` limited this peer to reading.
+    /// The server application should refuse their submissions.
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     /// Get remote socket
     #[must_use]
     pub fn remote_socket_addr(&self) -> SocketAddr {
         self.remote_socket_addr
     }
 
+    /// Get the options applied to new `Channel`s
+    #[must_use]
+    pub fn channel_options(&self) -> ChannelOptions {
+        self.channel_options
+    }
+
+    /// Set the options applied to new `Channel`s
+    pub fn set_channel_options(&mut self, channel_options: ChannelOptions) {
+        self.channel_options = channel_options;
+    }
+
     /// Close down gracefully.
     ///
     /// `message` will be truncated if it does not fit in a single packet
-    pub fn close(self, code: u32, message: &[u8]) {
+    pub fn close(self, code: CloseCode, message: &[u8]) {
         self.inner.close(code.into(), message);
     }
 
@@ -329,6 +707,22 @@ impl ClientConnection {
     /// Returns an Err if there was a QUIC `accept_bi()` problem
     pub async fn next_channel(&self) -> Result<Channel, Error> {
         let (send, recv) = self.inner.accept_bi().await?;
-        Ok(Channel::new(send, recv))
+        Ok(Channel::new(
+            send,
+            recv,
+            self.channel_options,
+            self.channels.open(),
+        ))
+    }
+
+    /// A snapshot of the statistics of the connection
+    #[must_use]
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats::new(
+            &self.inner,
+            &self.channels,
+            Some(self.handshake_duration),
+            self.established,
+        )
     }
 }
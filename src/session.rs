@@ -0,0 +1,48 @@
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore};
+use std::sync::Arc;
+
+/// Storage for TLS session tickets, so that a `Client` reconnecting to a
+/// server it has connected to before can resume the session instead of doing
+/// a full handshake, and can send early data.
+///
+/// Cloning a `SessionStore` shares the underlying storage, so the same store
+/// can be given to many `ClientConfig`s.
+///
+/// # Persistence
+///
+/// Tickets are only kept for the life of the process. There is no on-disk
+/// store, and no way to plug in your own: rustls (0.23) gives no way to
+/// serialize a stored session, so any store could only hold tickets in
+/// memory, which `in_memory` already does. The first connection to each
+/// server after a restart therefore does a full handshake and cannot send
+/// early data.
+#[derive(Debug, Clone)]
+pub struct SessionStore(Arc<dyn ClientSessionStore>);
+
+impl SessionStore {
+    /// An in-memory store remembering tickets for up to `max_servers` servers
+    #[must_use]
+    pub fn in_memory(max_servers: usize) -> SessionStore {
+        SessionStore(Arc::new(ClientSessionMemoryCache::new(max_servers)))
+    }
+
+    pub(crate) fn inner(&self) -> Arc<dyn ClientSessionStore> {
+        self.0.clone()
+    }
+}
+
+/// What happened to messages offered as 0-RTT early data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EarlyData {
+    /// The server accepted the early data. The messages were delivered before
+    /// the handshake completed.
+    Accepted,
+
+    /// The server rejected the early data. The messages were sent again after
+    /// the handshake completed.
+    Rejected,
+
+    /// Early data was not possible (it is not enabled, or we have no ticket
+    /// for this server). The messages were sent after the handshake completed.
+    NotAttempted,
+}
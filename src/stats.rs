@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A snapshot of the statistics of a connection
+///
+/// Take one with `Client::stats` or `ClientConnection::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The current best estimate of the round trip time
+    pub rtt: Duration,
+
+    /// The current congestion window, in bytes
+    pub congestion_window: u64,
+
+    /// Bytes sent, in UDP datagrams, including QUIC overhead
+    pub bytes_sent: u64,
+
+    /// Bytes received, in UDP datagrams, including QUIC overhead
+    pub bytes_received: u64,
+
+    /// UDP datagrams sent
+    pub packets_sent: u64,
+
+    /// UDP datagrams received
+    pub packets_received: u64,
+
+    /// QUIC packets deemed lost
+    pub lost_packets: u64,
+
+    /// `Channel`s of this connection that have not been dropped
+    pub open_channels: usize,
+
+    /// How long the handshake took, if it has completed
+    pub handshake_duration: Option<Duration>,
+
+    /// How long since the connection was established
+    pub age: Duration,
+}
+
+impl ConnectionStats {
+    pub(crate) fn new(
+        connection: &quinn::Connection,
+        channels: &ChannelCounter,
+        handshake_duration: Option<Duration>,
+        established: Instant,
+    ) -> ConnectionStats {
+        let stats = connection.stats();
+        ConnectionStats {
+            rtt: stats.path.rtt,
+            congestion_window: stats.path.cwnd,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            packets_sent: stats.udp_tx.datagrams,
+            packets_received: stats.udp_rx.datagrams,
+            lost_packets: stats.path.lost_packets,
+            open_channels: channels.count(),
+            handshake_duration,
+            age: established.elapsed(),
+        }
+    }
+}
+
+// Counts the `Channel`s of a connection
+#[derive(Debug, Default)]
+pub(crate) struct ChannelCounter(Arc<AtomicUsize>);
+
+impl ChannelCounter {
+    pub(crate) fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    // Count a new `Channel`, until the guard is dropped
+    pub(crate) fn open(&self) -> ChannelGuard {
+        let _ = self.0.fetch_add(1, Ordering::Relaxed);
+        ChannelGuard(self.0.clone())
+    }
+}
+
+// Held (shared by both halves) by a `Channel` while it is counted
+#[derive(Debug)]
+pub(crate) struct ChannelGuard(Arc<AtomicUsize>);
+
+impl Drop for ChannelGuard {
+    fn drop(&mut self) {
+        let _ = self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,181 @@
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+use quinn::{IdleTimeout, MtuDiscoveryConfig, TransportConfig, VarInt};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A QUIC congestion control algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CongestionController {
+    /// CUBIC (RFC 8312), the default
+    #[default]
+    Cubic,
+
+    /// `NewReno` (RFC 6582)
+    NewReno,
+
+    /// BBR (experimental)
+    Bbr,
+}
+
+/// Tuning for the QUIC transport underneath a `Server` or `Client`
+///
+/// Anything left unset keeps the QUIC library's default, which is tuned for a
+/// 100Mbps link with a 100ms round trip time. The idle timeout defaults to 30
+/// seconds and keep-alives are off by default.
+///
+/// ```no_run
+/// use mosaic_net::TransportOptions;
+/// use std::time::Duration;
+///
+/// let mobile = TransportOptions::new()
+///     .idle_timeout(Some(Duration::from_secs(60)))
+///     .keep_alive_interval(Some(Duration::from_secs(20)))
+///     .initial_rtt(Duration::from_millis(300));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportOptions {
+    idle_timeout: Option<Duration>,
+    keep_alive_interval: Option<Duration>,
+    stream_receive_window: Option<u64>,
+    receive_window: Option<u64>,
+    send_window: Option<u64>,
+    congestion_controller: Option<CongestionController>,
+    initial_rtt: Option<Duration>,
+    mtu_discovery: Option<bool>,
+    mtu_upper_bound: Option<u16>,
+}
+
+impl Default for TransportOptions {
+    fn default() -> TransportOptions {
+        TransportOptions {
+            idle_timeout: Some(Duration::from_secs(30)),
+            keep_alive_interval: None,
+            stream_receive_window: None,
+            receive_window: None,
+            send_window: None,
+            congestion_controller: None,
+            initial_rtt: None,
+            mtu_discovery: None,
+            mtu_upper_bound: None,
+        }
+    }
+}
+
+impl TransportOptions {
+    /// Create `TransportOptions` that leave everything at its default
+    #[must_use]
+    pub fn new() -> TransportOptions {
+        TransportOptions::default()
+    }
+
+    /// Close the connection after this long without any activity.
+    /// `None` means never. The peer's setting applies if it is shorter.
+    #[must_use]
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> TransportOptions {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Send a keep-alive this often when nothing else is being sent, to keep
+    /// the connection (and any NAT mappings) alive. `None` means never.
+    #[must_use]
+    pub fn keep_alive_interval(mut self, interval: Option<Duration>) -> TransportOptions {
+        self.keep_alive_interval = interval;
+        self
+    }
+
+    /// How many bytes the peer may send on a single `Channel` before we
+    /// read them
+    #[must_use]
+    pub fn stream_receive_window(mut self, bytes: u64) -> TransportOptions {
+        self.stream_receive_window = Some(bytes);
+        self
+    }
+
+    /// How many bytes the peer may send across all `Channel`s before we
+    /// read them
+    #[must_use]
+    pub fn receive_window(mut self, bytes: u64) -> TransportOptions {
+        self.receive_window = Some(bytes);
+        self
+    }
+
+    /// How many bytes we may buffer across all `Channel`s before the peer
+    /// acknowledges them
+    #[must_use]
+    pub fn send_window(mut self, bytes: u64) -> TransportOptions {
+        self.send_window = Some(bytes);
+        self
+    }
+
+    /// Which congestion control algorithm to use
+    #[must_use]
+    pub fn congestion_controller(mut self, controller: CongestionController) -> TransportOptions {
+        self.congestion_controller = Some(controller);
+        self
+    }
+
+    /// The round trip time to assume before it has been measured
+    #[must_use]
+    pub fn initial_rtt(mut self, rtt: Duration) -> TransportOptions {
+        self.initial_rtt = Some(rtt);
+        self
+    }
+
+    /// Whether to probe for a larger path MTU than the minimum
+    #[must_use]
+    pub fn mtu_discovery(mut self, enabled: bool) -> TransportOptions {
+        self.mtu_discovery = Some(enabled);
+        self
+    }
+
+    /// The largest UDP payload size MTU discovery will try
+    #[must_use]
+    pub fn mtu_upper_bound(mut self, bytes: u16) -> TransportOptions {
+        self.mtu_upper_bound = Some(bytes);
+        self
+    }
+
+    /// Apply these options to a quinn `TransportConfig`
+    pub(crate) fn apply(&self, transport_config: &mut TransportConfig) {
+        let _ = transport_config.max_idle_timeout(self.idle_timeout.map(|d| {
+            let millis = u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+            IdleTimeout::from(varint(millis))
+        }));
+        let _ = transport_config.keep_alive_interval(self.keep_alive_interval);
+        if let Some(bytes) = self.stream_receive_window {
+            let _ = transport_config.stream_receive_window(varint(bytes));
+        }
+        if let Some(bytes) = self.receive_window {
+            let _ = transport_config.receive_window(varint(bytes));
+        }
+        if let Some(bytes) = self.send_window {
+            let _ = transport_config.send_window(bytes);
+        }
+        if let Some(controller) = self.congestion_controller {
+            let _ =
+                match controller {
+                    CongestionController::Cubic => transport_config
+                        .congestion_controller_factory(Arc::new(CubicConfig::default())),
+                    CongestionController::NewReno => transport_config
+                        .congestion_controller_factory(Arc::new(NewRenoConfig::default())),
+                    CongestionController::Bbr => transport_config
+                        .congestion_controller_factory(Arc::new(BbrConfig::default())),
+                };
+        }
+        if let Some(rtt) = self.initial_rtt {
+            let _ = transport_config.initial_rtt(rtt);
+        }
+        if self.mtu_discovery == Some(false) {
+            let _ = transport_config.mtu_discovery_config(None);
+        } else if let Some(upper_bound) = self.mtu_upper_bound {
+            let mut mtu_discovery_config = MtuDiscoveryConfig::default();
+            let _ = mtu_discovery_config.upper_bound(upper_bound);
+            let _ = transport_config.mtu_discovery_config(Some(mtu_discovery_config));
+        }
+    }
+}
+
+fn varint(n: u64) -> VarInt {
+    VarInt::from_u64(n).unwrap_or(VarInt::MAX)
+}
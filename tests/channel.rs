@@ -0,0 +1,142 @@
+//! `Channel` protections against misbehaving peers, over localhost
+
+use mosaic_core::{PublicKey, SecretKey};
+use mosaic_net::*;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// A server on a free localhost port, with `channel_options`
+fn server(channel_options: ChannelOptions) -> (Server, PublicKey, SocketAddr) {
+    let socket_addr = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let secret_key = SecretKey::generate();
+    let public_key = secret_key.public();
+    let mut config = ServerConfig::new(secret_key, socket_addr).unwrap();
+    config.channel_options = channel_options;
+    (Server::new(config).unwrap(), public_key, socket_addr)
+}
+
+// Connect a client with `channel_options` to `server`
+async fn connect(
+    server: &Server,
+    server_public_key: PublicKey,
+    server_socket: SocketAddr,
+    channel_options: ChannelOptions,
+) -> (Client, ClientConnection) {
+    let mut config = ClientConfig::new(server_public_key, server_socket, None).unwrap();
+    config.channel_options = channel_options;
+    let (client, connection) = tokio::join!(config.client(None), async {
+        server
+            .accept()
+            .await
+            .unwrap()
+            .accept(&AlwaysAllowedApprover)
+            .await
+    });
+    (client.unwrap(), connection.unwrap())
+}
+
+// The header of a message `len` bytes long, body not included
+fn header(len: u32) -> [u8; 8] {
+    let mut header = [0; 8];
+    header[4..8].copy_from_slice(&len.to_le_bytes());
+    header
+}
+
+#[tokio::test]
+async fn oversize_message_is_rejected_and_stream_stopped() {
+    let options = ChannelOptions {
+        max_message_size: 1024,
+        ..ChannelOptions::default()
+    };
+    let (server, key, addr) = server(options);
+    let (client, connection) = connect(&server, key, addr, ChannelOptions::default()).await;
+
+    // Announce a 1 MiB message, without sending it
+    let (mut send, _recv) = client.inner().open_bi().await.unwrap();
+    send.write_all(&header(1024 * 1024)).await.unwrap();
+
+    let mut channel = connection.next_channel().await.unwrap();
+    let e = channel.recv().await.unwrap_err();
+    assert!(matches!(
+        e.inner,
+        InnerError::MessageTooLarge(1_048_576, 1024)
+    ));
+    assert!(channel.recv().await.unwrap().is_none());
+
+    let code = send.stopped().await.unwrap().map(CloseCode::from);
+    assert_eq!(code, Some(CloseCode::MessageTooLarge));
+}
+
+#[tokio::test]
+async fn message_up_to_the_limit_is_not_rejected() {
+    let options = ChannelOptions {
+        max_message_size: 1024,
+        ..ChannelOptions::default()
+    };
+    let (server, key, addr) = server(options);
+    let (client, connection) = connect(&server, key, addr, ChannelOptions::default()).await;
+
+    // Exactly at the limit, so it is read (and fails only to parse)
+    let (mut send, _recv) = client.inner().open_bi().await.unwrap();
+    send.write_all(&header(1024)).await.unwrap();
+    send.write_all(&[0; 1024 - 8]).await.unwrap();
+    send.finish().unwrap();
+
+    let mut channel = connection.next_channel().await.unwrap();
+    if let Err(e) = channel.recv().await {
+        assert!(!matches!(e.inner, InnerError::MessageTooLarge(..)), "{e}");
+    }
+}
+
+#[tokio::test]
+async fn stalled_message_times_out_and_stream_stopped() {
+    let options = ChannelOptions {
+        message_timeout: Some(Duration::from_millis(200)),
+        ..ChannelOptions::default()
+    };
+    let (server, key, addr) = server(options);
+    let (client, connection) = connect(&server, key, addr, ChannelOptions::default()).await;
+
+    // Start a message, then stall part way through its header
+    let (mut send, _recv) = client.inner().open_bi().await.unwrap();
+    send.write_all(&header(64)[..4]).await.unwrap();
+
+    let mut channel = connection.next_channel().await.unwrap();
+    let e = channel.recv().await.unwrap_err();
+    assert!(matches!(e.inner, InnerError::MessageTimeout), "{e}");
+    assert!(channel.recv().await.unwrap().is_none());
+
+    let code = send.stopped().await.unwrap().map(CloseCode::from);
+    assert_eq!(code, Some(CloseCode::Timeout));
+}
+
+#[tokio::test]
+async fn idle_channel_times_out() {
+    let options = ChannelOptions {
+        idle_timeout: Some(Duration::from_millis(200)),
+        ..ChannelOptions::default()
+    };
+    let (server, key, addr) = server(ChannelOptions::default());
+    let (client, _connection) = connect(&server, key, addr, options).await;
+
+    // The server never sends anything
+    let started = tokio::time::Instant::now();
+    let mut channel = client.new_channel().await.unwrap();
+    let e = channel.recv().await.unwrap_err();
+    assert!(matches!(e.inner, InnerError::ChannelIdleTimeout), "{e}");
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(channel.recv().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn no_timeouts_by_default() {
+    let (server, key, addr) = server(ChannelOptions::default());
+    let (client, _connection) = connect(&server, key, addr, ChannelOptions::default()).await;
+
+    let mut channel = client.new_channel().await.unwrap();
+    let waited = tokio::time::timeout(Duration::from_millis(300), channel.recv()).await;
+    assert!(waited.is_err());
+}